@@ -0,0 +1,315 @@
+//! `groq`, a command line client for quick checks and scripts against the groq API.
+//! Built with the `cli` feature.
+//!
+//! ```sh
+//! # one-shot prompt, streamed to stdout
+//! groq "Explain the importance of fast language models"
+//! # prompt read from stdin, the whole response printed as JSON
+//! cat notes.md | groq --system "Summarize the text" --json
+//! # interactive chat, the history is kept in chat.json between runs
+//! groq --session chat.json
+//! ```
+//! The API key is read from `--api-key` or the `GROQ_API_KEY` environment variable.
+use std::{
+    io::{BufRead, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use groq_api_rs::completion::{
+    client::Groq,
+    conversation::Conversation,
+    interceptor::Interceptor,
+    message::Message,
+    request::{builder::RequestBuilder, preset::Presets, preset::DEFAULT_PRESET, Request},
+    response::StreamResponse,
+};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MODEL: &str = "llama-3.1-8b-instant";
+
+const HELP: &str = "commands:
+  /save <file>   save the conversation
+  /load <file>   replace the conversation with a saved one
+  /clear         forget the conversation, keeping the system prompt
+  /history       print the conversation
+  /exit          quit (also ctrl-d)";
+
+/// Chat with groq from the terminal.
+///
+/// Sends the prompt given as arguments or piped on stdin and prints the answer. Without a prompt,
+/// or with --interactive, starts a chat where every line is sent with the previous turns.
+#[derive(Debug, Parser)]
+#[command(name = "groq", version)]
+struct Args {
+    /// Prompt to send, joined with spaces
+    prompt: Vec<String>,
+
+    #[arg(long, env = "GROQ_API_KEY", hide_env_values = true)]
+    api_key: String,
+
+    /// Model to use [default: llama-3.1-8b-instant, or the model of the preset]
+    #[arg(short, long, env = "GROQ_MODEL")]
+    model: Option<String>,
+
+    /// Sampling temperature, between 0 and 2
+    #[arg(short, long)]
+    temperature: Option<f32>,
+
+    /// Maximum number of tokens of every answer
+    #[arg(long)]
+    max_tokens: Option<u32>,
+
+    /// System prompt of new conversations
+    #[arg(short, long)]
+    system: Option<String>,
+
+    /// Preset file (json, or toml / yaml when built with those features)
+    #[arg(long)]
+    presets: Option<PathBuf>,
+
+    /// Preset of --presets to use
+    #[arg(long, default_value = DEFAULT_PRESET, requires = "presets")]
+    preset: String,
+
+    /// Wait for the whole answer instead of streaming it
+    #[arg(long)]
+    no_stream: bool,
+
+    /// Print every response as JSON, implies --no-stream
+    #[arg(long)]
+    json: bool,
+
+    /// Load the conversation from this file when it exists and save it after every turn
+    #[arg(long)]
+    session: Option<PathBuf>,
+
+    /// Keep chatting after the prompt given as arguments, chat lines are read from stdin even
+    /// when it is not a terminal
+    #[arg(short, long)]
+    interactive: bool,
+}
+
+/// Conversation as written by `--session` and `/save`
+#[derive(Debug, Serialize, Deserialize)]
+struct Session {
+    model: String,
+    messages: Vec<Message>,
+}
+
+/// Prints the content of every chunk as soon as it arrives
+#[derive(Debug)]
+struct PrintChunks;
+
+impl Interceptor for PrintChunks {
+    fn on_chunk(&self, _req: &Request, chunk: &mut StreamResponse) -> anyhow::Result<()> {
+        if let Some(content) = chunk
+            .choices
+            .first()
+            .and_then(|choice| choice.delta.content.as_deref())
+        {
+            let mut out = std::io::stdout().lock();
+            out.write_all(content.as_bytes())?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+struct Chat<'a> {
+    args: &'a Args,
+    request: RequestBuilder,
+    conversation: Conversation<'a>,
+}
+
+impl Chat<'_> {
+    fn reset(&mut self) {
+        self.conversation.clear_messages();
+        if let Some(system) = &self.args.system {
+            self.conversation.add_message(Message::SystemMessage {
+                content: Some(system.clone()),
+                name: None,
+                role: Some("system".to_string()),
+                tool_call_id: None,
+            });
+        }
+    }
+
+    async fn send(&mut self, prompt: String) -> anyhow::Result<()> {
+        let user = Message::UserMessage {
+            content: Some(prompt),
+            name: None,
+            role: Some("user".to_string()),
+            tool_call_id: None,
+        };
+        // only kept in the history once answered, so a failed turn can simply be retried
+        self.conversation.add_disposable_msg(user.clone());
+        let response = self
+            .conversation
+            .create(self.request.clone())
+            .await?
+            .into_response()?;
+
+        let content = response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.clone())
+            .unwrap_or_default();
+        if self.args.json {
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        } else if self.request.is_stream() {
+            println!();
+        } else {
+            println!("{}", content);
+        }
+
+        self.conversation.add_messages(vec![
+            user,
+            Message::AssistantMessage {
+                content: Some(content),
+                name: None,
+                role: Some("assistant".to_string()),
+                tool_calls: None,
+                tool_call_id: None,
+            },
+        ]);
+        if let Some(path) = &self.args.session {
+            self.save(path)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let session = Session {
+            model: self.request.model().to_string(),
+            messages: self.conversation.messages().to_vec(),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&session)?)?;
+        Ok(())
+    }
+
+    fn load(&mut self, path: &Path) -> anyhow::Result<()> {
+        let session: Session = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        self.conversation.clear_messages();
+        self.conversation.add_messages(session.messages);
+        Ok(())
+    }
+
+    fn history(&self) {
+        for msg in self.conversation.messages() {
+            let role = match msg {
+                Message::SystemMessage { .. } => "system",
+                Message::UserMessage { .. } => "user",
+                Message::AssistantMessage { .. } => "assistant",
+                Message::ToolMessage { .. } => "tool",
+            };
+            println!("[{}] {}", role, msg.content().unwrap_or_default());
+        }
+    }
+
+    async fn repl(&mut self) -> anyhow::Result<()> {
+        let mut lines = std::io::stdin().lock().lines();
+        loop {
+            print!("> ");
+            std::io::stdout().flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                println!();
+                return Ok(());
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some(command) = line.strip_prefix('/') else {
+                if let Err(err) = self.send(line.to_string()).await {
+                    eprintln!("error: {:#}", err);
+                }
+                continue;
+            };
+            let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+            let arg = Path::new(arg.trim());
+            let res = match command {
+                "exit" | "quit" => return Ok(()),
+                "clear" => {
+                    self.reset();
+                    Ok(())
+                }
+                "history" => {
+                    self.history();
+                    Ok(())
+                }
+                "save" | "load" if arg.as_os_str().is_empty() => {
+                    Err(anyhow::anyhow!("/{} needs a file", command))
+                }
+                "save" => self.save(arg),
+                "load" => self.load(arg),
+                _ => {
+                    println!("{}", HELP);
+                    Ok(())
+                }
+            };
+            if let Err(err) = res {
+                eprintln!("error: {:#}", err);
+            }
+        }
+    }
+}
+
+fn request(args: &Args) -> anyhow::Result<RequestBuilder> {
+    let mut req = match &args.presets {
+        Some(path) => Presets::load(path)?.builder(&args.preset)?,
+        None => RequestBuilder::new(DEFAULT_MODEL.to_string()),
+    };
+    if let Some(model) = &args.model {
+        req = req.with_model(model);
+    }
+    if let Some(temperature) = args.temperature {
+        req = req.with_temperature(temperature);
+    }
+    if let Some(max_tokens) = args.max_tokens {
+        req = req.with_max_tokens(max_tokens);
+    }
+    Ok(req.with_stream(!args.no_stream && !args.json))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let request = request(&args)?;
+    let mut client = Groq::new(&args.api_key);
+    if request.is_stream() {
+        client = client.with_interceptor(PrintChunks);
+    }
+
+    let mut chat = Chat {
+        args: &args,
+        request,
+        conversation: client.conversation(),
+    };
+    chat.reset();
+    if let Some(path) = args.session.as_deref().filter(|path| path.exists()) {
+        chat.load(path)?;
+    }
+
+    let prompt = if !args.prompt.is_empty() {
+        Some(args.prompt.join(" "))
+    } else if !args.interactive && !std::io::stdin().is_terminal() {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        Some(input)
+    } else {
+        None
+    };
+
+    match prompt {
+        Some(prompt) => {
+            chat.send(prompt).await?;
+            if args.interactive {
+                chat.repl().await?;
+            }
+        }
+        None => chat.repl().await?,
+    }
+    Ok(())
+}
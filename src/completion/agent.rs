@@ -0,0 +1,626 @@
+//! A ReAct style agent loop on top of [`Groq::create`]: the model answers or calls tools, the
+//! tools run, their output is sent back, until the model answers or a budget runs out.
+use std::{fmt::Debug, future::Future, sync::Arc};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client::Groq,
+    message::{Message, ToolCall},
+    request::{builder::RequestBuilder, Function, Tool},
+};
+
+/// A tool the model of an [`Agent`] can call.
+///
+/// The output of a call is sent back to the model as is. A failing call does not stop the agent,
+/// the error is sent to the model instead so it can correct its arguments.
+pub trait AgentTool: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// JSON schema of the arguments
+    fn parameters(&self) -> serde_json::Value;
+
+    fn call(&self, arguments: serde_json::Value) -> BoxFuture<'_, anyhow::Result<String>>;
+
+    fn definition(&self) -> Tool {
+        //! The tool as declared in the request
+        Tool {
+            tool_type: "function".into(),
+            function: Function {
+                description: Some(self.description().into()),
+                name: Some(self.name().into()),
+                parameters: Some(self.parameters()),
+            },
+        }
+    }
+}
+
+type CallFn = dyn Fn(serde_json::Value) -> BoxFuture<'static, anyhow::Result<String>> + Send + Sync;
+
+/// Tool backed by an async closure.
+/// ```ignore no_run
+/// let weather = FnTool::new(
+///     "weather",
+///     "Current weather of a city",
+///     json!({"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]}),
+///     |args| async move { fetch_weather(args["city"].as_str().unwrap_or_default()).await },
+/// );
+/// ```
+#[derive(Clone)]
+pub struct FnTool {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+    call: Arc<CallFn>,
+}
+
+impl FnTool {
+    pub fn new<F, Fut>(
+        name: &str,
+        description: &str,
+        parameters: serde_json::Value,
+        call: F,
+    ) -> Self
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<String>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+            call: Arc::new(move |args| Box::pin(call(args))),
+        }
+    }
+}
+
+impl Debug for FnTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnTool")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AgentTool for FnTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        self.parameters.clone()
+    }
+
+    fn call(&self, arguments: serde_json::Value) -> BoxFuture<'_, anyhow::Result<String>> {
+        (self.call)(arguments)
+    }
+}
+
+/// What an [`Agent`] remembers from one run to the next.
+/// - None, every run starts from the system prompt
+/// - Window, the last `n` inputs and their answers
+/// - Full, every input and its answer
+///
+/// Only the inputs and the final answers are kept, not the tool calls in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentMemory {
+    #[default]
+    None,
+    Window(usize),
+    Full,
+}
+
+/// Why a run stopped.
+/// - Answered, the model answered without calling a tool
+/// - MaxSteps, the step budget ran out
+/// - TokenBudget, the token budget ran out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Answered,
+    MaxSteps,
+    TokenBudget,
+}
+
+/// Progress of a run, given to the listeners installed with [`Agent::with_listener`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    /// A completion call is about to be sent, steps are counted from 1
+    Step {
+        step: usize,
+    },
+    /// The model answered, with text, tool calls or both
+    Response {
+        message: Message,
+        tokens: u64,
+    },
+    ToolCall {
+        id: Option<String>,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    /// The output of a tool call, or the error sent back to the model when it failed
+    Observation {
+        id: Option<String>,
+        name: String,
+        output: String,
+        failed: bool,
+    },
+    Finished {
+        stop: StopReason,
+    },
+}
+
+type Listener = dyn Fn(&AgentEvent) + Send + Sync;
+
+/// An agent: a system prompt, tools, the request parameters of its model and a memory.
+///
+/// [`Agent::run`] loops until the model answers. [`Agent::start`] runs the same loop one step at
+/// a time, e.g. to inspect the transcript between steps.
+/// ```ignore no_run
+/// let mut agent = Agent::new(client, RequestBuilder::new("llama-3.3-70b-versatile".into()))
+///     .with_system("You answer questions about the weather.")
+///     .with_tool(weather)
+///     .with_max_steps(5)
+///     .with_listener(|event| println!("{:?}", event));
+/// let outcome = agent.run("Do I need an umbrella in Paris?").await?;
+/// println!("{}", outcome.answer.unwrap_or_default());
+/// ```
+/// - max_steps, completion calls per run, 10 by default
+/// - max_tokens, total tokens per run, unlimited by default. Usage is only known once a call is
+///   done, so the last call may overrun it
+#[derive(Clone)]
+pub struct Agent {
+    client: Groq,
+    request: RequestBuilder,
+    system: Option<String>,
+    tools: Vec<Arc<dyn AgentTool>>,
+    memory: AgentMemory,
+    remembered: Vec<Message>,
+    max_steps: usize,
+    max_tokens: Option<u64>,
+    listeners: Vec<Arc<Listener>>,
+}
+
+impl Debug for Agent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Agent")
+            .field("request", &self.request)
+            .field("system", &self.system)
+            .field("tools", &self.tools)
+            .field("memory", &self.memory)
+            .field("max_steps", &self.max_steps)
+            .field("max_tokens", &self.max_tokens)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Agent {
+    pub fn new(client: Groq, request: RequestBuilder) -> Self {
+        Self {
+            client,
+            request,
+            system: None,
+            tools: Vec::new(),
+            memory: AgentMemory::None,
+            remembered: Vec::new(),
+            max_steps: 10,
+            max_tokens: None,
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn with_system(mut self, prompt: &str) -> Self {
+        self.system = Some(prompt.into());
+        self
+    }
+
+    pub fn with_tool(mut self, tool: impl AgentTool + 'static) -> Self {
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    pub fn with_memory(mut self, memory: AgentMemory) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    pub fn with_max_steps(mut self, steps: usize) -> Self {
+        self.max_steps = steps;
+        self
+    }
+
+    pub fn with_max_tokens(mut self, tokens: u64) -> Self {
+        self.max_tokens = Some(tokens);
+        self
+    }
+
+    pub fn with_listener(mut self, listener: impl Fn(&AgentEvent) + Send + Sync + 'static) -> Self {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    pub fn memory(&self) -> &[Message] {
+        //! The messages of previous runs sent before the input of the next run
+        &self.remembered
+    }
+
+    pub fn clear_memory(&mut self) {
+        self.remembered.clear();
+    }
+
+    pub fn start(&self, input: &str) -> AgentRun<'_> {
+        //! Starts a run without sending anything, drive it with [`AgentRun::step`].
+        //! The memory is not updated by step by step runs, see [`Agent::remember`].
+        AgentRun {
+            agent: self,
+            transcript: vec![Message::UserMessage {
+                content: Some(input.into()),
+                name: None,
+                role: Some("user".to_string()),
+                tool_call_id: None,
+            }],
+            steps: 0,
+            tokens: 0,
+            stop: None,
+        }
+    }
+
+    pub async fn run(&mut self, input: &str) -> anyhow::Result<AgentOutcome> {
+        //! Runs the loop until the model answers or a budget runs out, then updates the memory.
+        //! Errors of the completion calls abort the run.
+        let mut run = self.start(input);
+        while run.step().await?.is_none() {}
+        let outcome = run.finish();
+        self.remember(&outcome);
+        Ok(outcome)
+    }
+
+    pub fn remember(&mut self, outcome: &AgentOutcome) {
+        //! Adds the input and the answer of a run to the memory, runs without an answer are
+        //! forgotten
+        let (Some(input), Some(answer)) = (outcome.transcript.first(), &outcome.answer) else {
+            return;
+        };
+        let keep = match self.memory {
+            AgentMemory::None => return,
+            AgentMemory::Window(runs) => runs * 2,
+            AgentMemory::Full => usize::MAX,
+        };
+        self.remembered.push(input.clone());
+        self.remembered.push(Message::AssistantMessage {
+            content: Some(answer.clone()),
+            name: None,
+            role: Some("assistant".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+        });
+        let excess = self.remembered.len().saturating_sub(keep);
+        self.remembered.drain(..excess);
+    }
+
+    fn emit(&self, event: AgentEvent) {
+        for listener in &self.listeners {
+            listener(&event);
+        }
+    }
+
+    fn request(&self) -> RequestBuilder {
+        if self.tools.is_empty() {
+            return self.request.clone();
+        }
+        self.request
+            .clone()
+            .with_tools(self.tools.iter().map(|tool| tool.definition()).collect())
+            .with_auto_tool_choice()
+    }
+
+    async fn call_tool(&self, call: &ToolCall) -> (String, anyhow::Result<String>) {
+        let name = call.function.name.clone().unwrap_or_default();
+        let Some(tool) = self.tools.iter().find(|tool| tool.name() == name) else {
+            return (
+                name.clone(),
+                Err(anyhow::anyhow!("unknown tool {:?}", name)),
+            );
+        };
+        let arguments: serde_json::Value =
+            match serde_json::from_str(call.function.arguments.as_deref().unwrap_or("{}")) {
+                Ok(arguments) => arguments,
+                Err(err) => return (name, Err(anyhow::anyhow!("invalid arguments: {}", err))),
+            };
+        self.emit(AgentEvent::ToolCall {
+            id: call.id.clone(),
+            name: name.clone(),
+            arguments: arguments.clone(),
+        });
+        (name, tool.call(arguments).await)
+    }
+}
+
+/// A run of an [`Agent`] in progress, see [`Agent::start`].
+#[derive(Debug)]
+pub struct AgentRun<'a> {
+    agent: &'a Agent,
+    transcript: Vec<Message>,
+    steps: usize,
+    tokens: u64,
+    stop: Option<StopReason>,
+}
+
+impl AgentRun<'_> {
+    pub fn transcript(&self) -> &[Message] {
+        //! The messages of the run so far: the input, then every answer and tool output
+        &self.transcript
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.tokens
+    }
+
+    pub async fn step(&mut self) -> anyhow::Result<Option<StopReason>> {
+        //! Sends one completion call and runs the tools it asks for.
+        //! Returns why the run stopped once it did, None while it goes on
+        if let Some(stop) = self.stop {
+            return Ok(Some(stop));
+        }
+        let agent = self.agent;
+        if self.steps >= agent.max_steps {
+            return Ok(Some(self.stop_with(StopReason::MaxSteps)));
+        }
+        if matches!(agent.max_tokens, Some(max) if self.tokens >= max) {
+            return Ok(Some(self.stop_with(StopReason::TokenBudget)));
+        }
+
+        self.steps += 1;
+        agent.emit(AgentEvent::Step { step: self.steps });
+        let mut messages = Vec::new();
+        if let Some(system) = &agent.system {
+            messages.push(Message::SystemMessage {
+                content: Some(system.clone()),
+                name: None,
+                role: Some("system".to_string()),
+                tool_call_id: None,
+            });
+        }
+        messages.extend(agent.remembered.iter().cloned());
+        messages.extend(self.transcript.iter().cloned());
+        let completion = agent.client.create(agent.request(), messages).await?;
+        let tokens = completion
+            .usage()
+            .map(|usage| usage.total_tokens as u64)
+            .unwrap_or_default();
+        self.tokens += tokens;
+
+        let response = completion.into_response()?;
+        let answer = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("completion without choices"))?
+            .message;
+        let calls = answer.tool_calls.unwrap_or_default();
+        let message = Message::AssistantMessage {
+            content: Some(answer.content).filter(|content| !content.is_empty()),
+            name: None,
+            role: Some("assistant".to_string()),
+            tool_calls: Some(calls.clone()).filter(|calls| !calls.is_empty()),
+            tool_call_id: None,
+        };
+        agent.emit(AgentEvent::Response {
+            message: message.clone(),
+            tokens,
+        });
+        self.transcript.push(message);
+        if calls.is_empty() {
+            return Ok(Some(self.stop_with(StopReason::Answered)));
+        }
+
+        for call in &calls {
+            let (name, res) = agent.call_tool(call).await;
+            let failed = res.is_err();
+            let output = res.unwrap_or_else(|err| format!("error: {:#}", err));
+            agent.emit(AgentEvent::Observation {
+                id: call.id.clone(),
+                name: name.clone(),
+                output: output.clone(),
+                failed,
+            });
+            self.transcript.push(Message::ToolMessage {
+                content: Some(output),
+                name: Some(name),
+                role: Some("tool".to_string()),
+                tool_call_id: call.id.clone(),
+            });
+        }
+        Ok(None)
+    }
+
+    fn stop_with(&mut self, stop: StopReason) -> StopReason {
+        self.stop = Some(stop);
+        self.agent.emit(AgentEvent::Finished { stop });
+        stop
+    }
+
+    pub fn finish(self) -> AgentOutcome {
+        //! Consuming
+        //! Ends the run where it is, a run that did not stop yet has no answer
+        let answer = match self.stop {
+            Some(StopReason::Answered) => self
+                .transcript
+                .last()
+                .and_then(|msg| msg.content())
+                .map(String::from),
+            _ => None,
+        };
+        AgentOutcome {
+            answer,
+            stop: self.stop,
+            steps: self.steps,
+            tokens: self.tokens,
+            transcript: self.transcript,
+        }
+    }
+}
+
+/// Result of a run.
+/// - answer, the final answer of the model, None when a budget ran out first
+/// - stop, why the run stopped, None for a step by step run finished early
+/// - steps, the completion calls sent
+/// - tokens, the total tokens used by those calls
+/// - transcript, the input, then every answer and tool output, in order
+#[derive(Debug, Clone)]
+pub struct AgentOutcome {
+    pub answer: Option<String>,
+    pub stop: Option<StopReason>,
+    pub steps: usize,
+    pub tokens: u64,
+    pub transcript: Vec<Message>,
+}
+
+#[cfg(test)]
+mod agent_test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Agent, AgentEvent, AgentMemory, FnTool, StopReason};
+    use crate::completion::{
+        client::Groq, message::Message, request::builder::RequestBuilder, transport::MockTransport,
+    };
+
+    fn add() -> FnTool {
+        FnTool::new(
+            "add",
+            "Adds two numbers",
+            serde_json::json!({"type": "object", "properties": {"a": {"type": "number"}, "b": {"type": "number"}}}),
+            |args| async move {
+                let sum =
+                    args["a"].as_f64().unwrap_or_default() + args["b"].as_f64().unwrap_or_default();
+                Ok(sum.to_string())
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn runs_tools_until_answered() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport
+            .push_tool_call("call_1", "add", r#"{"a":1,"b":2}"#)
+            .push_tool_call("call_2", "mul", "{}")
+            .push_completion("1 + 2 = 3");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let mut agent = Agent::new(
+            Groq::new("key").with_transport(transport.clone()),
+            RequestBuilder::new("m".into()),
+        )
+        .with_system("You can add.")
+        .with_tool(add())
+        .with_listener(move |event| seen.lock().unwrap().push(event.clone()));
+
+        let outcome = agent.run("what is 1 + 2?").await?;
+        assert_eq!(outcome.answer.as_deref(), Some("1 + 2 = 3"));
+        assert_eq!(outcome.stop, Some(StopReason::Answered));
+        assert_eq!((outcome.steps, outcome.tokens), (3, 6));
+        let roles: Vec<_> = outcome
+            .transcript
+            .iter()
+            .map(|msg| match msg {
+                Message::UserMessage { .. } => "user",
+                Message::AssistantMessage { .. } => "assistant",
+                Message::ToolMessage { .. } => "tool",
+                Message::SystemMessage { .. } => "system",
+            })
+            .collect();
+        assert_eq!(
+            roles,
+            [
+                "user",
+                "assistant",
+                "tool",
+                "assistant",
+                "tool",
+                "assistant"
+            ]
+        );
+        assert_eq!(outcome.transcript[2].content(), Some("3"));
+        assert_eq!(
+            outcome.transcript[4].content(),
+            Some(r#"error: unknown tool "mul""#)
+        );
+
+        let events = events.lock().unwrap();
+        assert!(events.contains(&AgentEvent::ToolCall {
+            id: Some("call_1".into()),
+            name: "add".into(),
+            arguments: serde_json::json!({"a": 1, "b": 2}),
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&AgentEvent::Finished {
+                stop: StopReason::Answered
+            })
+        );
+
+        let sent = transport.requests();
+        assert_eq!(sent[0].body["tools"][0]["function"]["name"], "add");
+        assert_eq!(sent[0].body["tool_choice"], "auto");
+        let second = sent[1].body["messages"].as_array().unwrap();
+        assert_eq!(second.len(), 4);
+        assert_eq!(second[3]["tool_call_id"], "call_1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stops_on_budgets_and_remembers_answers() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        let mut agent = Agent::new(
+            Groq::new("key").with_transport(transport.clone()),
+            RequestBuilder::new("m".into()),
+        )
+        .with_tool(add())
+        .with_max_steps(2)
+        .with_memory(AgentMemory::Window(1));
+
+        for _ in 0..2 {
+            transport.push_tool_call("call", "add", "{}");
+        }
+        let outcome = agent.run("loop").await?;
+        assert_eq!(outcome.stop, Some(StopReason::MaxSteps));
+        assert_eq!(outcome.answer, None);
+        assert!(agent.memory().is_empty());
+
+        let mut agent = agent.with_max_steps(10).with_max_tokens(3);
+        transport
+            .push_completion("first")
+            .push_tool_call("call", "add", "{}")
+            .push_tool_call("call", "add", "{}");
+        agent.run("one").await?;
+        let outcome = agent.run("two").await?;
+        assert_eq!(outcome.stop, Some(StopReason::TokenBudget));
+        assert_eq!(outcome.steps, 2);
+        let sent = transport.requests();
+        let messages = sent[3].body["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["content"], "one");
+        assert_eq!(messages[1]["content"], "first");
+        assert_eq!(messages[2]["content"], "two");
+        assert_eq!(agent.memory().len(), 2);
+
+        let mut run = agent.start("three");
+        transport.push_completion("done");
+        assert_eq!(run.step().await?, Some(StopReason::Answered));
+        assert_eq!(run.transcript().len(), 2);
+        assert_eq!(run.finish().answer.as_deref(), Some("done"));
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+//! Folding of stream chunks back into a complete [`Response`].
+use std::collections::BTreeMap;
+
+use super::{
+    client::CompletionOption,
+    logprobs::LogProbs,
+    message::{AssistantFunc, ToolCall},
+    response::{Choice, ChoiceMessage, Response, StreamResponse, UsageInfo},
+};
+
+/// Reassembles the chunks of a stream completion into the [`Response`] a non stream request
+/// would have returned.
+/// - choices are grouped by their index, content and tool call arguments are concatenated in
+///   arrival order
+/// - the log probabilities of the chunks are concatenated as well
+/// - the usage is taken from the last chunk carrying `x_groq.usage`; when no chunk carried one
+///   (e.g. the chunks of an interrupted stream) the response reports zero tokens, check
+///   [`StreamAggregator::usage`] before finishing to tell both cases apart
+/// ```ignore no_run
+/// let mut aggregator = StreamAggregator::new();
+/// for chunk in &chunks {
+///     aggregator.push(chunk);
+/// }
+/// let res = aggregator.finish()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StreamAggregator {
+    head: Option<Head>,
+    choices: BTreeMap<u32, PartialChoice>,
+    usage: Option<UsageInfo>,
+}
+
+/// Response level fields, taken from the first chunk
+#[derive(Debug, Clone)]
+struct Head {
+    id: String,
+    object: String,
+    created: chrono::DateTime<chrono::Utc>,
+    model: String,
+    system_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct PartialChoice {
+    role: Option<String>,
+    content: String,
+    tool_calls: BTreeMap<u32, ToolCall>,
+    finish_reason: Option<String>,
+    logprobs: Option<LogProbs>,
+}
+
+impl StreamAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &StreamResponse) {
+        //! Non Consuming
+        //! Folds a single chunk into the aggregate
+        if self.head.is_none() {
+            self.head = Some(Head {
+                id: chunk.id.clone(),
+                object: "chat.completion".to_string(),
+                created: chunk.created,
+                model: chunk.model.clone(),
+                system_fingerprint: chunk.system_fingerprint.clone(),
+            });
+        }
+        if let Some(usage) = chunk
+            .x_groq
+            .as_ref()
+            .and_then(|x_groq| x_groq.usage.clone())
+        {
+            self.usage = Some(usage);
+        }
+
+        for choice in &chunk.choices {
+            let partial = self.choices.entry(choice.index).or_default();
+            let delta = &choice.delta;
+            if let Some(role) = &delta.role {
+                partial.role = Some(role.clone());
+            }
+            if let Some(content) = &delta.content {
+                partial.content.push_str(content);
+            }
+            for call in delta.tool_calls.iter().flatten() {
+                let tool_call = partial.tool_calls.entry(call.index).or_insert(ToolCall {
+                    id: None,
+                    tool_type: None,
+                    function: AssistantFunc {
+                        arguments: None,
+                        name: None,
+                    },
+                });
+                if call.id.is_some() {
+                    tool_call.id.clone_from(&call.id);
+                }
+                if call.tool_type.is_some() {
+                    tool_call.tool_type.clone_from(&call.tool_type);
+                }
+                if let Some(function) = &call.function {
+                    if function.name.is_some() {
+                        tool_call.function.name.clone_from(&function.name);
+                    }
+                    if let Some(arguments) = &function.arguments {
+                        tool_call
+                            .function
+                            .arguments
+                            .get_or_insert_with(String::new)
+                            .push_str(arguments);
+                    }
+                }
+            }
+            if choice.finish_reason.is_some() {
+                partial.finish_reason.clone_from(&choice.finish_reason);
+            }
+            if let Some(logprobs) = &choice.logprobs {
+                partial
+                    .logprobs
+                    .get_or_insert_with(LogProbs::default)
+                    .extend(logprobs);
+            }
+        }
+    }
+
+    pub fn usage(&self) -> Option<&UsageInfo> {
+        //! Usage of the last chunk carrying `x_groq.usage`, None while no chunk carried one
+        self.usage.as_ref()
+    }
+
+    pub fn finish(self) -> anyhow::Result<Response> {
+        //! Consuming
+        //! Builds the final response, errors when no chunk was pushed.
+        //! A choice that never received a role is reported as `assistant`, one that never
+        //! received a finish reason has an empty `finish_reason`. Without any usage the response
+        //! reports zero tokens, see [`StreamAggregator::usage`].
+        let Some(head) = self.head else {
+            anyhow::bail!("cannot aggregate a stream without any chunk");
+        };
+        let choices = self
+            .choices
+            .into_iter()
+            .map(|(index, partial)| Choice {
+                index,
+                message: ChoiceMessage {
+                    role: partial.role.unwrap_or_else(|| "assistant".to_string()),
+                    content: partial.content,
+                    tool_calls: if partial.tool_calls.is_empty() {
+                        None
+                    } else {
+                        Some(partial.tool_calls.into_values().collect())
+                    },
+                },
+                finish_reason: partial.finish_reason.unwrap_or_default(),
+                logprobs: partial.logprobs,
+            })
+            .collect();
+
+        Ok(Response {
+            id: head.id,
+            object: head.object,
+            created: head.created,
+            model: head.model,
+            system_fingerprint: head.system_fingerprint,
+            choices,
+            usage: self.usage.unwrap_or_default(),
+        })
+    }
+}
+
+impl Response {
+    pub fn from_stream(chunks: &[StreamResponse]) -> anyhow::Result<Self> {
+        //! Shorthand for feeding `chunks` through a [`StreamAggregator`]
+        let mut aggregator = StreamAggregator::new();
+        for chunk in chunks {
+            aggregator.push(chunk);
+        }
+        aggregator.finish()
+    }
+}
+
+impl CompletionOption {
+    pub fn into_response(self) -> anyhow::Result<Response> {
+        //! Consuming
+        //! Returns the response of either completion kind, aggregating the chunks of a stream
+        match self {
+            CompletionOption::NonStream(res) => Ok(res),
+            CompletionOption::Stream(chunks) => Response::from_stream(&chunks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod aggregate_test {
+    use crate::completion::{
+        aggregate::StreamAggregator,
+        client::CompletionOption,
+        response::{Response, StreamResponse},
+    };
+
+    fn chunk(choices: &str, x_groq: &str) -> anyhow::Result<StreamResponse> {
+        Ok(serde_json::from_str(&format!(
+            r#"{{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":"fp_1","choices":[{}],"x_groq":{}}}"#,
+            choices, x_groq
+        ))?)
+    }
+
+    #[test]
+    fn folds_interleaved_choices_and_tool_calls() -> anyhow::Result<()> {
+        let chunks = vec![
+            chunk(
+                r#"{"index":1,"delta":{"role":"assistant","content":""},"logprobs":null,"finish_reason":null},
+                   {"index":0,"delta":{"role":"assistant","content":"Hel"},"logprobs":null,"finish_reason":null}"#,
+                r#"{"id":"req_1"}"#,
+            )?,
+            chunk(
+                r#"{"index":1,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"weather","arguments":"{\"ci"}}]},"logprobs":null,"finish_reason":null},
+                   {"index":0,"delta":{"content":"lo"},"logprobs":null,"finish_reason":null}"#,
+                "null",
+            )?,
+            chunk(
+                r#"{"index":1,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"ty\":\"Paris\"}"}}]},"logprobs":null,"finish_reason":"tool_calls"},
+                   {"index":0,"delta":{},"logprobs":null,"finish_reason":"stop"}"#,
+                r#"{"id":"req_1","usage":{"prompt_tokens":5,"completion_tokens":7,"total_tokens":12,"prompt_time":0.1,"completion_time":0.2,"total_time":0.3}}"#,
+            )?,
+        ];
+
+        let res = CompletionOption::Stream(chunks).into_response()?;
+        assert_eq!(res.object, "chat.completion");
+        assert_eq!(res.system_fingerprint.as_deref(), Some("fp_1"));
+        assert_eq!(res.usage.total_tokens, 12);
+        assert_eq!(res.choices.len(), 2);
+
+        let text = &res.choices[0];
+        assert_eq!(text.index, 0);
+        assert_eq!(text.message.role, "assistant");
+        assert_eq!(text.message.content, "Hello");
+        assert_eq!(text.finish_reason, "stop");
+        assert!(text.message.tool_calls.is_none());
+
+        let tool = &res.choices[1];
+        assert_eq!(tool.finish_reason, "tool_calls");
+        let calls = tool.message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id.as_deref(), Some("call_1"));
+        assert_eq!(calls[0].tool_type.as_deref(), Some("function"));
+        assert_eq!(calls[0].function.name.as_deref(), Some("weather"));
+        assert_eq!(
+            calls[0].function.arguments.as_deref(),
+            Some(r#"{"city":"Paris"}"#)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_usage_is_reported_by_the_aggregator() -> anyhow::Result<()> {
+        let mut aggregator = StreamAggregator::new();
+        aggregator.push(&chunk(
+            r#"{"index":0,"delta":{"role":"assistant","content":"Hi"},"logprobs":null,"finish_reason":"stop"}"#,
+            r#"{"id":"req_1"}"#,
+        )?);
+        assert!(aggregator.usage().is_none());
+        assert_eq!(aggregator.finish()?.usage.total_tokens, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn empty_stream_is_an_error() {
+        assert!(Response::from_stream(&[]).is_err());
+    }
+}
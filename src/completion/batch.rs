@@ -0,0 +1,128 @@
+use futures::StreamExt;
+
+use super::{
+    client::{CompletionOption, Groq},
+    message::Message,
+    request::builder::RequestBuilder,
+    retry::{RateLimiter, RetryPolicy},
+};
+
+/// Options of [`Groq::create_many`].
+/// - concurrency, the maximum number of requests in flight (at least 1)
+/// - retry, the policy applied to every item individually
+/// - requests_per_minute, optional cap on how many requests are started per minute, retries included
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BatchOptions {
+    pub concurrency: usize,
+    pub retry: RetryPolicy,
+    pub requests_per_minute: Option<u32>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            retry: RetryPolicy::default(),
+            requests_per_minute: None,
+        }
+    }
+}
+
+impl BatchOptions {
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_requests_per_minute(mut self, rpm: u32) -> Self {
+        self.requests_per_minute = Some(rpm);
+        self
+    }
+}
+
+impl Groq {
+    pub async fn create_many<I>(
+        &self,
+        items: I,
+        options: &BatchOptions,
+    ) -> Vec<anyhow::Result<CompletionOption>>
+    where
+        I: IntoIterator<Item = (RequestBuilder, Vec<Message>)>,
+    {
+        //! Runs independent completions with at most `options.concurrency` in flight.
+        //! The results are in the same order as `items` and a failed item does not abort the
+        //! others, its error is returned in its slot once its retries are exhausted.
+        let limiter = options.requests_per_minute.map(RateLimiter::new);
+        let limiter = limiter.as_ref();
+        futures::stream::iter(items)
+            .map(|(req, messages)| async move {
+                self.create_retrying(&req, &messages, &options.retry, limiter)
+                    .await
+            })
+            .buffered(options.concurrency.max(1))
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod batch_test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::BatchOptions;
+    use crate::completion::{
+        client::{CompletionOption, Groq},
+        message::Message,
+        request::builder::RequestBuilder,
+        retry::RetryPolicy,
+        transport::{fixtures, MockTransport},
+    };
+
+    fn item(prompt: &str) -> (RequestBuilder, Vec<Message>) {
+        (
+            RequestBuilder::new("m".into()),
+            vec![fixtures::user(prompt)],
+        )
+    }
+
+    #[tokio::test]
+    async fn results_keep_input_order_with_per_item_errors() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        let rate_limited = r#"{"error":{"message":"slow down","type":"rate_limit_error"}}"#;
+        let invalid = r#"{"error":{"message":"bad","type":"invalid_request_error"}}"#;
+        // concurrency 1 makes the order in which replies are consumed deterministic
+        transport
+            .push_completion("first")
+            .push_json(429, rate_limited)
+            .push_completion("second")
+            .push_json(400, invalid);
+
+        let client = Groq::new("key").with_transport(transport.clone());
+        let options = BatchOptions::default()
+            .with_concurrency(1)
+            .with_retry(RetryPolicy {
+                max_retries: 2,
+                initial_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            });
+        let results = client
+            .create_many(vec![item("a"), item("b"), item("c")], &options)
+            .await;
+
+        assert_eq!(results.len(), 3);
+        let content = |res: &anyhow::Result<CompletionOption>| match res {
+            Ok(CompletionOption::NonStream(res)) => Some(res.choices[0].message.content.clone()),
+            _ => None,
+        };
+        assert_eq!(content(&results[0]).as_deref(), Some("first"));
+        assert_eq!(content(&results[1]).as_deref(), Some("second"));
+        assert!(results[2].is_err());
+        assert_eq!(transport.requests().len(), 4);
+        Ok(())
+    }
+}
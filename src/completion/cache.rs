@@ -0,0 +1,373 @@
+//! Response cache sitting in front of [`Groq::create`](super::client::Groq::create).
+//!
+//! Completions are stored under a stable key derived from the serialized request, so identical
+//! deterministic requests (temperature 0, fixed `seed`, ...) are only sent once. Stream
+//! completions are stored chunk by chunk and replayed as [`CompletionOption::Stream`].
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::{client::CompletionOption, fingerprint::Fingerprint};
+
+/// How a request uses the cache of the client, set with
+/// [`RequestBuilder::with_cache_mode`](super::request::builder::RequestBuilder::with_cache_mode).
+/// - Use, serve from the cache when possible and store the answer otherwise (default)
+/// - Refresh, always send the request and overwrite the cached answer
+/// - Bypass, neither read nor write the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CacheMode {
+    #[default]
+    Use,
+    Refresh,
+    Bypass,
+}
+
+/// A completion stored in a [`CacheStore`] along with the time it was stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub stored_at: chrono::DateTime<Utc>,
+    pub completion: CompletionOption,
+}
+
+/// Storage backend of a [`ResponseCache`].
+pub trait CacheStore: Debug + Send + Sync {
+    fn get(&self, key: &str) -> anyhow::Result<Option<CacheEntry>>;
+
+    fn put(&self, key: &str, entry: CacheEntry) -> anyhow::Result<()>;
+
+    fn remove(&self, key: &str) -> anyhow::Result<()>;
+}
+
+/// In memory [`CacheStore`] evicting the least recently used entry once `capacity` is reached.
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    tick: u64,
+    entries: HashMap<String, (u64, CacheEntry)>,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<CacheEntry>> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        Ok(state.entries.get_mut(key).map(|(used, entry)| {
+            *used = tick;
+            entry.clone()
+        }))
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        if !state.entries.contains_key(key) && state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.entries.insert(key.to_string(), (tick, entry));
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.state.lock().unwrap().entries.remove(key);
+        Ok(())
+    }
+}
+
+/// [`CacheStore`] keeping every entry as a json file named after its key in a directory,
+/// so the cache survives restarts and can be shared between processes.
+#[derive(Debug, Clone)]
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    pub fn new(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        //! Creates the directory when it does not exist yet
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<CacheEntry>> {
+        match fs::read_to_string(self.path(key)) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) -> anyhow::Result<()> {
+        // write then rename so that concurrent readers never see a partial file
+        let tmp = self.dir.join(format!("{}.json.tmp", key));
+        fs::write(&tmp, serde_json::to_vec(&entry)?)?;
+        fs::rename(tmp, self.path(key))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Cache of completions installed on a client with
+/// [`Groq::with_cache`](super::client::Groq::with_cache).
+/// - store, where the entries live ([`MemoryStore`], [`DiskStore`] or a custom [`CacheStore`])
+/// - ttl, how long an entry stays valid, forever when None
+///
+/// Only successful completions are stored, a stream is stored once it reached `[DONE]`.
+/// The cache is best-effort: a store failing to read or write an entry never fails the call, the
+/// request is sent to groq instead (reported as a warning with the `tracing` feature).
+#[derive(Debug)]
+pub struct ResponseCache {
+    store: Box<dyn CacheStore>,
+    ttl: Option<Duration>,
+}
+
+impl ResponseCache {
+    pub fn new(store: impl CacheStore + 'static) -> Self {
+        Self {
+            store: Box::new(store),
+            ttl: None,
+        }
+    }
+
+    pub fn memory(capacity: usize) -> Self {
+        //! In memory LRU cache holding at most `capacity` completions
+        Self::new(MemoryStore::new(capacity))
+    }
+
+    pub fn disk(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        //! Cache persisted as json files in `dir`
+        Ok(Self::new(DiskStore::new(dir)?))
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn key(request_body: &serde_json::Value) -> String {
+        //! Stable key of a serialized request, its canonical [`Fingerprint`]
+        request_body.fingerprint()
+    }
+
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<CompletionOption>> {
+        //! Returns the cached completion, dropping it when its ttl elapsed
+        let Some(entry) = self.store.get(key)? else {
+            return Ok(None);
+        };
+        if let Some(ttl) = self.ttl {
+            let age = (Utc::now() - entry.stored_at).to_std().unwrap_or_default();
+            if age >= ttl {
+                self.store.remove(key)?;
+                return Ok(None);
+            }
+        }
+        Ok(Some(entry.completion))
+    }
+
+    pub fn put(&self, key: &str, completion: &CompletionOption) -> anyhow::Result<()> {
+        self.store.put(
+            key,
+            CacheEntry {
+                stored_at: Utc::now(),
+                completion: completion.clone(),
+            },
+        )
+    }
+
+    pub fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.store.remove(key)
+    }
+}
+
+#[cfg(test)]
+mod cache_test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{CacheEntry, CacheMode, CacheStore, MemoryStore, ResponseCache};
+    use crate::completion::{
+        client::{CompletionOption, Groq},
+        message::Message,
+        request::builder::RequestBuilder,
+        transport::{fixtures, MockTransport},
+    };
+
+    fn messages() -> Vec<Message> {
+        vec![fixtures::user("hello")]
+    }
+
+    fn request() -> RequestBuilder {
+        RequestBuilder::new("m".into())
+            .with_temperature(0.0)
+            .with_seed(7)
+    }
+
+    #[test]
+    fn memory_store_evicts_least_recently_used() -> anyhow::Result<()> {
+        let store = MemoryStore::new(2);
+        let entry = CacheEntry {
+            stored_at: chrono::Utc::now(),
+            completion: CompletionOption::Stream(Vec::new()),
+        };
+        store.put("a", entry.clone())?;
+        store.put("b", entry.clone())?;
+        assert!(store.get("a")?.is_some());
+        store.put("c", entry)?;
+        assert_eq!(store.len(), 2);
+        assert!(store.get("a")?.is_some());
+        assert!(store.get("b")?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identical_requests_hit_the_cache() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_completion("hi").push_completion("hi");
+        let client = Groq::new("key")
+            .with_transport(transport.clone())
+            .with_cache(ResponseCache::memory(8));
+
+        client.create(request(), messages()).await?;
+        let cached = client.create(request(), messages()).await?;
+        assert!(
+            matches!(cached, CompletionOption::NonStream(res) if res.choices[0].message.content == "hi")
+        );
+        assert_eq!(transport.requests().len(), 1);
+
+        client
+            .create(request().with_cache_mode(CacheMode::Bypass), messages())
+            .await?;
+        assert_eq!(transport.requests().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streams_are_replayed_as_chunks() -> anyhow::Result<()> {
+        let chunk = r#"{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{"index":0,"delta":{"content":"hi"},"logprobs":null,"finish_reason":null}],"x_groq":{"id":"req_1"}}"#;
+        let transport = Arc::new(MockTransport::new());
+        transport.push_events(vec![
+            chunk.to_string(),
+            chunk.to_string(),
+            "[DONE]".to_string(),
+        ]);
+
+        let dir = std::env::temp_dir().join(format!(
+            "groq_api_rs_streams_are_replayed_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let client = Groq::new("key")
+            .with_transport(transport.clone())
+            .with_cache(ResponseCache::disk(&dir)?.with_ttl(Duration::from_secs(60)));
+
+        let req = || request().with_stream(true);
+        client.create(req(), messages()).await?;
+        let cached = client.create(req(), messages()).await?;
+        assert!(matches!(cached, CompletionOption::Stream(chunks) if chunks.len() == 2));
+        assert_eq!(transport.requests().len(), 1);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct BrokenStore;
+
+    impl CacheStore for BrokenStore {
+        fn get(&self, _: &str) -> anyhow::Result<Option<CacheEntry>> {
+            anyhow::bail!("disk full")
+        }
+
+        fn put(&self, _: &str, _: CacheEntry) -> anyhow::Result<()> {
+            anyhow::bail!("disk full")
+        }
+
+        fn remove(&self, _: &str) -> anyhow::Result<()> {
+            anyhow::bail!("disk full")
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_stores_do_not_fail_calls() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_completion("hi").push_completion("hi");
+        let client = Groq::new("key")
+            .with_transport(transport.clone())
+            .with_cache(ResponseCache::new(BrokenStore));
+
+        client.create(request(), messages()).await?;
+        client.create(request(), messages()).await?;
+        assert_eq!(transport.requests().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn truncated_streams_are_not_cached() -> anyhow::Result<()> {
+        let chunk = r#"{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{"index":0,"delta":{"content":"hi"},"logprobs":null,"finish_reason":null}],"x_groq":{"id":"req_1"}}"#;
+        let transport = Arc::new(MockTransport::new());
+        transport
+            .push_events(vec![chunk.to_string()])
+            .push_events(vec![chunk.to_string(), "[DONE]".to_string()]);
+        let client = Groq::new("key")
+            .with_transport(transport.clone())
+            .with_cache(ResponseCache::memory(8));
+
+        let req = || request().with_stream(true);
+        client.create(req(), messages()).await?;
+        client.create(req(), messages()).await?;
+        assert_eq!(transport.requests().len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use futures::{future::BoxFuture, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use zeroize::Zeroize;
+
+use super::transport::{
+    is_credential, EventStream, HttpRequest, HttpResponse, MultipartRequest, SseEvent, StreamReply,
+    Transport,
+};
+
+/// Placeholder written to cassettes in place of the API key.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Whether a [`Cassette`] captures live traffic or serves previously captured traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CassetteMode {
+    /// Requests hit groq and every exchange is appended to the cassette file.
+    Record,
+    /// Requests never leave the process, they are answered from the cassette file.
+    Replay,
+}
+
+/// Body of a recorded response.
+/// - Text, the raw body of a json response (successful or not)
+/// - Events, the data field of every Server Sent Event in the order they were received
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(tag = "kind", content = "data", rename_all = "snake_case")]
+pub enum RecordedBody {
+    Text(String),
+    Events(Vec<String>),
+}
+
+/// A single request / response pair stored in a cassette.
+/// # Note
+/// - The `authorization` header is always stored redacted.
+/// - The response headers of a successful SSE exchange are not exposed by the event source and
+///   are therefore left empty.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Interaction {
+    pub request: serde_json::Value,
+    pub request_headers: Vec<(String, String)>,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    pub body: RecordedBody,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Default)]
+struct CassetteState {
+    interactions: Vec<Interaction>,
+    used: Vec<bool>,
+}
+
+/// Record-and-replay storage for the HTTP exchanges made by [`Groq`](super::client::Groq).
+///
+/// In [`CassetteMode::Record`] the client talks to groq as usual and writes every exchange,
+/// including SSE event sequences, to a json file. Stream events are handed over as they arrive,
+/// the exchange is written once the stream completed.
+/// In [`CassetteMode::Replay`] the client answers requests from that file by matching on the
+/// serialized [`Request`](super::request::Request). Each recorded interaction is served once,
+/// so identical requests are replayed in the order they were recorded.
+/// A request without a matching interaction fails with an error instead of reaching the network.
+/// ```ignore no_run
+/// let client = Groq::new(api_key).with_cassette(Cassette::replaying("tests/cassettes/chat.json")?);
+/// ```
+#[derive(Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    state: Mutex<CassetteState>,
+}
+
+impl Cassette {
+    pub fn recording(path: impl AsRef<Path>) -> Self {
+        //! Creates a cassette in record mode.
+        //! The file at `path` is overwritten by the first recorded interaction.
+        Self {
+            path: path.as_ref().to_path_buf(),
+            mode: CassetteMode::Record,
+            state: Mutex::new(CassetteState::default()),
+        }
+    }
+
+    pub fn replaying(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        //! Loads a previously recorded cassette in replay mode.
+        let path = path.as_ref().to_path_buf();
+        let file: CassetteFile = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        let used = vec![false; file.interactions.len()];
+        Ok(Self {
+            path,
+            mode: CassetteMode::Replay,
+            state: Mutex::new(CassetteState {
+                interactions: file.interactions,
+                used,
+            }),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn interactions(&self) -> Vec<Interaction> {
+        //! Returns a copy of the interactions currently held by the cassette
+        self.state.lock().unwrap().interactions.clone()
+    }
+
+    /// Finds the first interaction recorded for `request` that has not been served yet.
+    pub(crate) fn replay(&self, request: &serde_json::Value) -> anyhow::Result<Interaction> {
+        let mut state = self.state.lock().unwrap();
+        let CassetteState { interactions, used } = &mut *state;
+        let hit = interactions
+            .iter()
+            .zip(used.iter_mut())
+            .find(|(interaction, used)| !**used && interaction.request == *request);
+        match hit {
+            Some((interaction, used)) => {
+                *used = true;
+                Ok(interaction.clone())
+            }
+            None => anyhow::bail!(
+                "no unused interaction in cassette '{}' matches request {}",
+                self.path.display(),
+                request
+            ),
+        }
+    }
+
+    /// Appends an interaction and flushes the whole cassette to disk.
+    pub(crate) fn record(&self, mut interaction: Interaction) -> anyhow::Result<()> {
+        interaction.request_headers = redact_headers(interaction.request_headers);
+        let mut state = self.state.lock().unwrap();
+        state.interactions.push(interaction);
+        state.used.push(true);
+        let file = CassetteFile {
+            interactions: state.interactions.clone(),
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+}
+
+/// [`Transport`] decorator that records the exchanges of the wrapped transport into a
+/// [`Cassette`], or answers them from it without touching the wrapped transport.
+/// Installed by [`Groq::with_cassette`](super::client::Groq::with_cassette).
+#[derive(Debug)]
+pub struct CassetteTransport {
+    inner: Arc<dyn Transport>,
+    cassette: Arc<Cassette>,
+}
+
+impl CassetteTransport {
+    pub fn new(inner: Arc<dyn Transport>, cassette: Cassette) -> Self {
+        Self {
+            inner,
+            cassette: Arc::new(cassette),
+        }
+    }
+
+    pub fn cassette(&self) -> &Cassette {
+        &self.cassette
+    }
+
+    fn replay(&self, request: &HttpRequest) -> anyhow::Result<Interaction> {
+        self.cassette.replay(&request.body)
+    }
+
+    fn interaction(mut request: HttpRequest, response: HttpResponse) -> Interaction {
+        Interaction {
+            request: request.body.take(),
+            request_headers: std::mem::take(&mut request.headers),
+            status: response.status,
+            response_headers: response.headers,
+            body: RecordedBody::Text(response.body),
+        }
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn send_json(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        Box::pin(async move {
+            if self.cassette.mode() == CassetteMode::Replay {
+                let interaction = self.replay(&request)?;
+                let RecordedBody::Text(body) = interaction.body else {
+                    anyhow::bail!("cassette holds an event stream for a json request");
+                };
+                return Ok(HttpResponse {
+                    status: interaction.status,
+                    headers: interaction.response_headers,
+                    body,
+                });
+            }
+
+            let response = self.inner.send_json(request.clone()).await?;
+            self.cassette
+                .record(Self::interaction(request, response.clone()))?;
+            Ok(response)
+        })
+    }
+
+    fn open_stream(&self, mut request: HttpRequest) -> BoxFuture<'_, anyhow::Result<StreamReply>> {
+        Box::pin(async move {
+            if self.cassette.mode() == CassetteMode::Replay {
+                let interaction = self.replay(&request)?;
+                return Ok(match interaction.body {
+                    RecordedBody::Events(events) => StreamReply::Events(
+                        futures::stream::iter(
+                            std::iter::once(SseEvent::Open)
+                                .chain(events.into_iter().map(SseEvent::Message))
+                                .map(Ok),
+                        )
+                        .boxed(),
+                    ),
+                    RecordedBody::Text(body) => StreamReply::Rejected(HttpResponse {
+                        status: interaction.status,
+                        headers: interaction.response_headers,
+                        body,
+                    }),
+                });
+            }
+
+            match self.inner.open_stream(request.clone()).await? {
+                StreamReply::Rejected(response) => {
+                    self.cassette
+                        .record(Self::interaction(request, response.clone()))?;
+                    Ok(StreamReply::Rejected(response))
+                }
+                StreamReply::Events(stream) => {
+                    let recording = Recording {
+                        cassette: self.cassette.clone(),
+                        request: request.body.take(),
+                        request_headers: redact_headers(std::mem::take(&mut request.headers)),
+                        events: Vec::new(),
+                    };
+                    Ok(StreamReply::Events(recording.forward(stream)))
+                }
+            }
+        })
+    }
+
+    fn send_multipart(
+        &self,
+        request: MultipartRequest,
+    ) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        //! Multipart exchanges are passed through, they are neither recorded nor replayed.
+        self.inner.send_multipart(request)
+    }
+}
+
+/// Event stream being recorded, the events are handed over as they arrive and the exchange is
+/// written to the cassette once groq sent `[DONE]` or closed the stream.
+/// A stream failing or dropped before that is not recorded.
+struct Recording {
+    cassette: Arc<Cassette>,
+    request: serde_json::Value,
+    request_headers: Vec<(String, String)>,
+    events: Vec<String>,
+}
+
+impl Recording {
+    fn forward(self, stream: EventStream) -> EventStream {
+        futures::stream::unfold(Some((stream, self)), |state| async move {
+            let (mut stream, mut recording) = state?;
+            let event = match stream.next().await {
+                Some(Ok(event)) => event,
+                Some(Err(err)) => return Some((Err(err), None)),
+                None => return recording.finish().err().map(|err| (Err(err), None)),
+            };
+            if let SseEvent::Message(data) = &event {
+                recording.events.push(data.clone());
+                if data == "[DONE]" {
+                    return Some((recording.finish().map(|()| event), None));
+                }
+            }
+            Some((Ok(event), Some((stream, recording))))
+        })
+        .boxed()
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        self.cassette.record(Interaction {
+            request: self.request,
+            request_headers: self.request_headers,
+            status: 200,
+            response_headers: Vec::new(),
+            body: RecordedBody::Events(self.events),
+        })
+    }
+}
+
+/// Replaces the value of credential bearing headers with [`REDACTED`], wiping the original value.
+pub fn redact_headers(headers: Vec<(String, String)>) -> Vec<(String, String)> {
+    headers
+        .into_iter()
+        .map(|(name, mut value)| {
+            if is_credential(&name) {
+                value.zeroize();
+                (name, format!("Bearer {}", REDACTED))
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod cassette_test {
+    use std::{path::PathBuf, sync::Arc};
+
+    use futures::StreamExt;
+
+    use super::{redact_headers, Cassette, CassetteTransport, Interaction, RecordedBody, REDACTED};
+    use crate::completion::transport::{
+        HttpRequest, MockTransport, SseEvent, StreamReply, Transport,
+    };
+
+    fn cassette_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("groq_api_rs_{}_{}.json", test, std::process::id()))
+    }
+
+    fn interaction(content: &str) -> Interaction {
+        Interaction {
+            request: serde_json::json!({ "model": "test", "stream": false }),
+            request_headers: vec![("authorization".into(), "Bearer secret".into())],
+            status: 200,
+            response_headers: Vec::new(),
+            body: RecordedBody::Text(content.into()),
+        }
+    }
+
+    #[test]
+    fn recorded_cassette_redacts_and_replays_in_order() -> anyhow::Result<()> {
+        let path = cassette_path("redacts_and_replays");
+        let recorder = Cassette::recording(&path);
+        recorder.record(interaction("first"))?;
+        recorder.record(interaction("second"))?;
+
+        let raw = std::fs::read_to_string(&path)?;
+        assert!(!raw.contains("secret"));
+        assert!(raw.contains(REDACTED));
+
+        let player = Cassette::replaying(&path)?;
+        let request = serde_json::json!({ "model": "test", "stream": false });
+        assert_eq!(
+            player.replay(&request)?.body,
+            RecordedBody::Text("first".into())
+        );
+        assert_eq!(
+            player.replay(&request)?.body,
+            RecordedBody::Text("second".into())
+        );
+        assert!(player.replay(&request).is_err());
+        assert!(player
+            .replay(&serde_json::json!({ "model": "other" }))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn only_authorization_is_redacted() {
+        let headers = redact_headers(vec![
+            ("Authorization".into(), "Bearer secret".into()),
+            ("accept".into(), "text/event-stream".into()),
+        ]);
+        assert_eq!(headers[0].1, format!("Bearer {}", REDACTED));
+        assert_eq!(headers[1].1, "text/event-stream");
+    }
+
+    #[tokio::test]
+    async fn recording_forwards_events_as_they_arrive() -> anyhow::Result<()> {
+        let path = cassette_path("forwards_events");
+        let mock = MockTransport::new();
+        mock.push_stalled_events(vec!["{}".into()])
+            .push_events(vec!["{}".into(), "[DONE]".into()]);
+        let transport = CassetteTransport::new(Arc::new(mock), Cassette::recording(&path));
+        let request = HttpRequest {
+            url: "https://api.groq.com/openai/v1/chat/completions".into(),
+            headers: vec![("authorization".into(), "Bearer secret".into())],
+            body: serde_json::json!({ "model": "test", "stream": true }),
+        };
+
+        // the first stream never completes, its events still come through
+        let StreamReply::Events(mut stalled) = transport.open_stream(request.clone()).await? else {
+            panic!("expected an event stream");
+        };
+        assert!(matches!(stalled.next().await, Some(Ok(SseEvent::Open))));
+        assert!(matches!(
+            stalled.next().await,
+            Some(Ok(SseEvent::Message(_)))
+        ));
+        drop(stalled);
+        assert!(transport.cassette().interactions().is_empty());
+
+        let StreamReply::Events(stream) = transport.open_stream(request).await? else {
+            panic!("expected an event stream");
+        };
+        assert_eq!(stream.count().await, 3);
+        let interactions = transport.cassette().interactions();
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(
+            interactions[0].body,
+            RecordedBody::Events(vec!["{}".into(), "[DONE]".into()])
+        );
+        assert!(!std::fs::read_to_string(&path)?.contains("secret"));
+        Ok(())
+    }
+}
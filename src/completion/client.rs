@@ -1,16 +1,34 @@
-use std::hash::{Hash, Hasher};
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
 
 use super::{
+    cache::{CacheMode, ResponseCache},
+    cassette::{Cassette, CassetteTransport},
+    conversation::Conversation,
+    credentials::{CredentialProvider, StaticCredential},
+    instrument::CallSpan,
+    interceptor::Interceptor,
+    keys::KeyPool,
+    ledger::UsageLedger,
     message::Message,
     request,
-    response::{ErrorResponse, Response},
+    response::{ErrorResponse, Response, UsageInfo},
+    stream::{is_reconnectable, resume_request, StreamGuard, StreamInterrupted},
+    timings::TimedCompletion,
+    transport::{
+        zeroize_credentials, EventStream, HttpRequest, HttpResponse, ReqwestTransport, SseEvent,
+        StreamReply, Transport, COMPLETIONS_URL,
+    },
 };
 use crate::completion::response::StreamResponse;
 use futures::StreamExt;
 use reqwest::header;
-use reqwest_eventsource::{Event, EventSource};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "data")]
 /// The returned response from groq's completion API could either be a json with full llm response
 /// or chunks of response sent via Server Sent Event(SSE)
 pub enum CompletionOption {
@@ -18,17 +36,45 @@ pub enum CompletionOption {
     Stream(Vec<StreamResponse>),
 }
 
+impl CompletionOption {
+    pub fn usage(&self) -> Option<&UsageInfo> {
+        //! Token usage reported by groq, carried by the last chunks of a stream completion
+        match self {
+            CompletionOption::NonStream(res) => Some(&res.usage),
+            CompletionOption::Stream(chunks) => chunks
+                .iter()
+                .rev()
+                .find_map(|chunk| chunk.x_groq.as_ref()?.usage.as_ref()),
+        }
+    }
+}
+
+/// Outcome of running a request through the interceptor chain
+enum Outgoing {
+    Send(HttpRequest),
+    ShortCircuit(CompletionOption),
+}
+
+/// Stateless client for groq's completion API.
+///
+/// The client is `Send + Sync` and cheap to clone (clones share the connection pool), so a single
+/// instance can serve many tokio tasks concurrently. Message history is kept by [`Conversation`].
+///
 /// # Private Fields
-/// - api_key, the API key used to authenticate with groq,
-/// - client, the reqwest::Client with built in connection pool,
-/// - tmp_messages, messages that stay there for only a single request. After the request they are cleared.
-/// - messages,  a Vec for containing messages send to the groq completion endpoint (historic messages will not clear after request)
+/// - credentials, where the API key used to authenticate with groq comes from,
+/// - transport, the HTTP stack, [`ReqwestTransport`] with its built in connection pool by default,
+/// - interceptors, the chain of [`Interceptor`] every request and response goes through, in order
+/// - cache, the [`ResponseCache`] serving repeated requests, if any
+/// - keys, the [`KeyPool`] used instead of `credentials`, if any
+/// - ledger, the [`UsageLedger`] recording the usage and enforcing the budgets, if any
 #[derive(Debug, Clone)]
 pub struct Groq {
-    api_key: String,
-    messages: Vec<Message>,
-    disposable_msgs: Vec<Message>,
-    client: reqwest::Client,
+    credentials: Arc<dyn CredentialProvider>,
+    transport: Arc<dyn Transport>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    cache: Option<Arc<ResponseCache>>,
+    keys: Option<Arc<KeyPool>>,
+    ledger: Option<Arc<UsageLedger>>,
 }
 
 impl Groq {
@@ -36,191 +82,480 @@ impl Groq {
         //! Returns an instance of Groq struct.
         //! ```ignore no_run
         //! Self {
-        //!     api_key: api_key.into(), // the API key used to authenticate with groq
-        //!     client: reqwest::Client::new(), // the reqwest::Client with built in connection pool
-        //!     messages: Vec::new() // a Vec for containing messages send to the groq completion endpoint (historic messages will not clear after request)
+        //!     credentials: Arc::new(StaticCredential(api_key.into())), // the API key used to authenticate with groq
+        //!     transport: Arc::new(ReqwestTransport::new()), // reqwest based HTTP stack with built in connection pool
+        //!     interceptors: Vec::new(), // no interceptors
+        //!     cache: None, // no response cache
+        //!     keys: None, // no key pool
+        //!     ledger: None, // no usage ledger
         //! }
         //! ```
         Self {
-            api_key: api_key.into(),
-            client: reqwest::Client::new(),
-            disposable_msgs: Vec::new(),
-            messages: Vec::new(),
+            credentials: Arc::new(StaticCredential(api_key.into())),
+            transport: Arc::new(ReqwestTransport::new()),
+            interceptors: Vec::new(),
+            cache: None,
+            keys: None,
+            ledger: None,
         }
     }
 
-    pub fn add_message(&mut self, msg: Message) {
-        //! Non Consuming
-        //! Adds a message to the internal message vector
-        self.messages.push(msg);
+    pub fn with_credentials(mut self, provider: impl CredentialProvider + 'static) -> Self {
+        //! Replaces the API key given to [`Groq::new`] with a provider asked before every request,
+        //! see [`CredentialProvider`]
+        self.credentials = Arc::new(provider);
+        self
     }
 
-    pub fn add_messages(&mut self, msgs: Vec<Message>) {
-        //! Non Consuming
-        //! Add messages to the internal message vector
-        self.messages.extend(msgs);
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        //! Replaces the HTTP stack used to talk to groq, see [`Transport`]
+        self.transport = Arc::new(transport);
+        self
     }
 
-    pub fn clear_messages(&mut self) {
-        //! Non Consuming
-        //! Clears the internal message vector.
-        //! And shrink the capacity to 3.
-        self.messages.clear();
-        self.messages.shrink_to(3);
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        //! Appends a layer to the interceptor chain, see [`Interceptor`] for the order of the hooks
+        self.interceptors.push(Arc::new(interceptor));
+        self
     }
 
-    /// Clears the internal tmp_messages vector.
-    /// # Note
-    /// Fucntion is created for internal use and is not recomended for external use.
-    pub fn clear_disposable_msgs_override(&mut self) {
-        //! Non Consuming
-        self.disposable_msgs.clear();
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        //! Serves repeated requests from `cache`, see [`ResponseCache`].
+        //! Clones of the client share the same cache.
+        self.cache = Some(Arc::new(cache));
+        self
     }
 
-    pub fn add_disposable_msgs(&mut self, msgs: Vec<Message>) {
-        //! Non Consuming
-        self.disposable_msgs.extend(msgs);
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        //! Wraps the current transport so that every HTTP exchange is recorded to or replayed
+        //! from the cassette, see [`CassetteTransport`].
+        //! Clones of the client share the same cassette.
+        self.transport = Arc::new(CassetteTransport::new(self.transport.clone(), cassette));
+        self
     }
 
-    pub fn add_disposable_msg(&mut self, msg: Message) {
-        //! Non Consuming
-        self.disposable_msgs.push(msg);
+    pub fn with_key_pool(mut self, pool: KeyPool) -> Self {
+        //! Sends every request with a key of `pool` instead of the key given to [`Groq::new`],
+        //! see [`KeyPool`]. Clones of the client share the same pool.
+        self.keys = Some(Arc::new(pool));
+        self
     }
 
-    fn get_disposable_msgs(&self) -> Option<Vec<Message>> {
-        if self.disposable_msgs.is_empty() {
-            None
-        } else {
-            Some(self.disposable_msgs.clone())
+    pub fn key_pool(&self) -> Option<&KeyPool> {
+        //! The pool installed by [`Groq::with_key_pool`], to inspect the health of its keys
+        self.keys.as_deref()
+    }
+
+    pub fn with_ledger(mut self, ledger: UsageLedger) -> Self {
+        //! Records the usage of every call in `ledger` and refuses the requests over its budgets,
+        //! see [`UsageLedger`]. Clones of the client share the same ledger.
+        self.ledger = Some(Arc::new(ledger));
+        self
+    }
+
+    pub fn ledger(&self) -> Option<&UsageLedger> {
+        //! The ledger installed by [`Groq::with_ledger`], to read or export the recorded usage
+        self.ledger.as_deref()
+    }
+
+    pub fn conversation(&self) -> Conversation<'_> {
+        //! Starts an empty [`Conversation`] sending its requests through this client
+        Conversation::new(self)
+    }
+
+    fn request_headers(&self, api_key: &str) -> Vec<(String, String)> {
+        vec![
+            (
+                header::AUTHORIZATION.to_string(),
+                format!("Bearer {}", api_key),
+            ),
+            (
+                header::CONTENT_TYPE.to_string(),
+                "application/json".to_string(),
+            ),
+        ]
+    }
+
+    /// Runs the request through the interceptor chain and prepares the HTTP request.
+    async fn intercept_request(
+        &self,
+        req: &mut request::Request,
+        api_key: &str,
+    ) -> anyhow::Result<Outgoing> {
+        let mut headers = self.request_headers(api_key);
+        for interceptor in &self.interceptors {
+            if let Some(completion) = interceptor.on_request(req, &mut headers).await? {
+                zeroize_credentials(&mut headers);
+                return Ok(Outgoing::ShortCircuit(completion));
+            }
         }
+        Ok(Outgoing::Send(HttpRequest {
+            url: COMPLETIONS_URL.to_string(),
+            headers,
+            body: serde_json::to_value(&*req)?,
+        }))
     }
 
-    /// Outputs the request messages that should be passed onto the request.
-    /// Utility function created for easier logic internally.
-    /// # Returns
-    /// - Vec<Message> in the form of vec!**<global messages, disposable messages>**
-    fn get_all_request_messages(&self) -> Vec<Message> {
-        if self.disposable_msgs.is_empty() {
-            self.messages.clone()
-        } else {
-            vec![self.messages.clone(), self.disposable_msgs.clone()].concat()
+    async fn intercept_response(
+        &self,
+        req: &request::Request,
+        res: &mut HttpResponse,
+    ) -> anyhow::Result<()> {
+        for interceptor in self.interceptors.iter().rev() {
+            interceptor.on_response(req, res).await?;
         }
+        Ok(())
     }
 
-    /// Outputs the request messages that should be passed onto the request and clears the tmp messages.
-    /// Utility function created for easier logic internally.
-    fn get_request_messages_with_disposable_clear(&mut self) -> Vec<Message> {
-        let all = self.get_all_request_messages();
-        self.clear_disposable_msgs_override();
-        all
+    async fn intercept_completion(
+        &self,
+        req: &request::Request,
+        mut completion: CompletionOption,
+    ) -> anyhow::Result<CompletionOption> {
+        for interceptor in self.interceptors.iter().rev() {
+            interceptor.on_completion(req, &mut completion).await?;
+        }
+        Ok(completion)
+    }
+
+    /// Key under which the answer to `http_req` is cached, None when the cache is not used.
+    fn cache_key(&self, http_req: &HttpRequest, mode: CacheMode) -> Option<String> {
+        match (&self.cache, mode) {
+            (Some(_), CacheMode::Use | CacheMode::Refresh) => {
+                Some(ResponseCache::key(&http_req.body))
+            }
+            _ => None,
+        }
+    }
+
+    /// The cache is best-effort: a failing lookup is reported on `call` and taken as a miss.
+    fn cache_lookup(
+        &self,
+        key: Option<&str>,
+        mode: CacheMode,
+        call: &CallSpan,
+    ) -> Option<CompletionOption> {
+        match (&self.cache, key, mode) {
+            (Some(cache), Some(key), CacheMode::Use) => cache.get(key).unwrap_or_else(|err| {
+                call.cache_failed(&err);
+                None
+            }),
+            _ => None,
+        }
+    }
+
+    /// A completion that cannot be stored is reported on `call` and still returned.
+    fn cache_store(&self, key: Option<&str>, completion: &CompletionOption, call: &CallSpan) {
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            if let Err(err) = cache.put(key, completion) {
+                call.cache_failed(&err);
+            }
+        }
+    }
+
+    /// Opens the event stream of `http_req`, turning a rejected stream into an [`ErrorResponse`].
+    /// `bufs` are the chunks received so far, handed over when a limit interrupts the opening.
+    async fn open_events(
+        &self,
+        req: &request::Request,
+        http_req: HttpRequest,
+        guard: &StreamGuard,
+        call: &mut CallSpan,
+        bufs: &mut Vec<StreamResponse>,
+    ) -> anyhow::Result<EventStream> {
+        let opened = guard
+            .run(self.transport.open_stream(http_req), false)
+            .await
+            .map_err(|reason| StreamInterrupted {
+                reason,
+                chunks: std::mem::take(bufs),
+            })?;
+        match opened? {
+            StreamReply::Events(stream) => {
+                call.status(reqwest::StatusCode::OK.as_u16());
+                Ok(stream)
+            }
+            StreamReply::Rejected(mut res) => {
+                call.status(res.status);
+                self.intercept_response(req, &mut res).await?;
+                anyhow::bail!(error_response(res.status, &res.body)?)
+            }
+        }
     }
 
     async fn create_stream_completion(
-        &mut self,
+        &self,
         req: request::builder::RequestBuilder,
+        messages: Vec<Message>,
+        api_key: &str,
+        call: &mut CallSpan,
     ) -> anyhow::Result<CompletionOption> {
-        /* REMARK:
-         * https://github.com/jpopesculian/reqwest-eventsource/
-         * https://parsec.cloud/en/how-the-reqwest-http-client-streams-responses-in-a-web-context/
-         */
-        let req = req
-            .with_messages(self.get_request_messages_with_disposable_clear())?
-            .build();
+        let guard = StreamGuard::new(req.stream_options());
+        let reconnect = req.stream_options().reconnect.clone();
+        let cache_mode = req.cache_mode();
+        let mut req = req.with_messages(messages)?.try_build()?;
         anyhow::ensure!(
             req.is_stream(),
             "'create_stream_completion' func must have the stream flag turned on in request body"
         );
-        let mut stream = EventSource::new(
-            self.client
-                .post("https://api.groq.com/openai/v1/chat/completions")
-                .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
-                .header(header::ACCEPT, "text/event-stream")
-                .json(&req),
-        )?;
+        let http_req = match self.intercept_request(&mut req, api_key).await? {
+            Outgoing::Send(http_req) => http_req,
+            Outgoing::ShortCircuit(completion) => return Ok(completion),
+        };
+        let cache_key = self.cache_key(&http_req, cache_mode);
+        if let Some(completion) = self.cache_lookup(cache_key.as_deref(), cache_mode, call) {
+            return self.intercept_completion(&req, completion).await;
+        }
         let mut bufs: Vec<StreamResponse> = Vec::new();
-        while let Some(event) = stream.next().await {
-            match event {
-                Ok(Event::Open) => println!("Connection Open!"),
-                Ok(Event::Message(message)) => {
-                    if message.data == "[DONE]" {
+        let mut stream = self
+            .open_events(&req, http_req.clone(), &guard, call, &mut bufs)
+            .await?;
+        let mut reconnects = 0;
+        // a stream closing before '[DONE]' is returned but never cached
+        let mut done = false;
+        loop {
+            let next = match guard.run(stream.next(), true).await {
+                Ok(next) => next,
+                Err(reason) => {
+                    // dropping the stream closes the underlying connection
+                    drop(stream);
+                    anyhow::bail!(StreamInterrupted {
+                        reason,
+                        chunks: bufs,
+                    });
+                }
+            };
+            let dropped = match next {
+                Some(Ok(SseEvent::Open)) => {
+                    call.stream_opened();
+                    continue;
+                }
+                Some(Ok(SseEvent::Message(data))) => {
+                    if data == "[DONE]" {
+                        done = true;
                         break;
                     }
-                    bufs.push(serde_json::from_str(&message.data)?);
+                    let mut chunk: StreamResponse = serde_json::from_str(&data)?;
+                    if chunk.carries_tokens() {
+                        call.tokens_received();
+                    }
+                    for interceptor in self.interceptors.iter().rev() {
+                        interceptor.on_chunk(&req, &mut chunk)?;
+                    }
+                    bufs.push(chunk);
+                    continue;
                 }
-                Err(err) => {
-                    stream.close();
-                    anyhow::bail!("Error: {}", err);
+                Some(Err(err)) => err,
+                // without a policy a stream closing early is taken as is
+                None if reconnect.is_some() => {
+                    anyhow::anyhow!("event stream closed before '[DONE]'")
                 }
-            }
+                None => break,
+            };
+
+            let Some(policy) = &reconnect else {
+                return Err(dropped);
+            };
+            let Some(resumed) = resume_request(&http_req, &bufs) else {
+                return Err(dropped);
+            };
+            stream = loop {
+                if reconnects >= policy.max_reconnects {
+                    return Err(dropped);
+                }
+                reconnects += 1;
+                call.retried();
+                if let Err(reason) = guard.run(tokio::time::sleep(policy.backoff), false).await {
+                    anyhow::bail!(StreamInterrupted {
+                        reason,
+                        chunks: bufs,
+                    });
+                }
+                match self
+                    .open_events(&req, resumed.clone(), &guard, call, &mut bufs)
+                    .await
+                {
+                    Ok(stream) => break stream,
+                    Err(err) if is_reconnectable(&err) => continue,
+                    Err(err) => return Err(err),
+                }
+            };
         }
-        stream.close();
 
-        Ok(CompletionOption::Stream(bufs))
+        let completion = CompletionOption::Stream(bufs);
+        if done {
+            self.cache_store(cache_key.as_deref(), &completion, call);
+        }
+        self.intercept_completion(&req, completion).await
     }
 
     async fn create_non_stream_completion(
-        &mut self,
+        &self,
         req: request::builder::RequestBuilder,
+        messages: Vec<Message>,
+        api_key: &str,
+        call: &mut CallSpan,
     ) -> anyhow::Result<CompletionOption> {
-        let req = req
-            .with_messages(self.get_request_messages_with_disposable_clear())?
-            .build();
-        let body = (self.client)
-            .post("https://api.groq.com/openai/v1/chat/completions")
-            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .json(&req)
-            .send()
-            .await?;
-        if body.status() == reqwest::StatusCode::OK {
-            Ok(CompletionOption::NonStream(body.json::<Response>().await?))
+        let cache_mode = req.cache_mode();
+        let mut req = req.with_messages(messages)?.try_build()?;
+        let http_req = match self.intercept_request(&mut req, api_key).await? {
+            Outgoing::Send(http_req) => http_req,
+            Outgoing::ShortCircuit(completion) => return Ok(completion),
+        };
+        let cache_key = self.cache_key(&http_req, cache_mode);
+        if let Some(completion) = self.cache_lookup(cache_key.as_deref(), cache_mode, call) {
+            return self.intercept_completion(&req, completion).await;
+        }
+        let mut res = self.transport.send_json(http_req).await?;
+        call.status(res.status);
+        self.intercept_response(&req, &mut res).await?;
+        if res.status == reqwest::StatusCode::OK.as_u16() {
+            let completion =
+                CompletionOption::NonStream(serde_json::from_str::<Response>(&res.body)?);
+            self.cache_store(cache_key.as_deref(), &completion, call);
+            self.intercept_completion(&req, completion).await
         } else {
-            let statcode = body.status();
-            let mut error: ErrorResponse = serde_json::from_str(&body.text().await?)?;
-            error.code = statcode;
-            anyhow::bail!(error)
+            anyhow::bail!(error_response(res.status, &res.body)?)
         }
     }
 
     pub async fn create(
-        &mut self,
+        &self,
         req: request::builder::RequestBuilder,
+        messages: Vec<Message>,
     ) -> anyhow::Result<CompletionOption> {
-        if !req.is_stream() {
-            self.create_non_stream_completion(req).await
-        } else {
-            self.create_stream_completion(req).await
+        //! Sends `messages` with the parameters of `req`.
+        //! Use a [`Conversation`] to keep a message history between requests.
+        //! Requests with a [`FallbackPolicy`](super::fallback::FallbackPolicy) go through
+        //! [`Groq::create_with_fallback`].
+        self.create_attempt(req, messages, 0)
+            .await
+            .map(|timed| timed.completion)
+    }
+
+    /// [`Groq::create`] as the given retry of a call, `retries` is reported on its span.
+    pub(crate) async fn create_attempt(
+        &self,
+        req: request::builder::RequestBuilder,
+        messages: Vec<Message>,
+        retries: u32,
+    ) -> anyhow::Result<TimedCompletion> {
+        if req.fallback().is_some() {
+            return self
+                .fallback_chain(req, messages, retries)
+                .await
+                .map(|(served, timings)| TimedCompletion {
+                    completion: served.completion,
+                    timings,
+                });
         }
+        self.create_timed(req, messages, retries).await
+    }
+
+    /// Runs a completion call, measuring its [`ClientTimings`](super::timings::ClientTimings) on the way.
+    pub(crate) async fn create_timed(
+        &self,
+        req: request::builder::RequestBuilder,
+        messages: Vec<Message>,
+        retries: u32,
+    ) -> anyhow::Result<TimedCompletion> {
+        let (model, user, tags) = (
+            req.model().to_string(),
+            req.user().map(String::from),
+            req.tags().to_vec(),
+        );
+        if let Some(ledger) = &self.ledger {
+            ledger.check(&model, user.as_deref(), &tags)?;
+        }
+        let lease = self.keys.as_ref().map(|pool| pool.acquire()).transpose()?;
+        let fetched;
+        let api_key = match &lease {
+            Some(lease) => lease.key(),
+            None => {
+                fetched = self.credentials.api_key().await?;
+                fetched.expose()
+            }
+        };
+        let mut call = CallSpan::start(&req, retries);
+        let scope = call.scope();
+        let res = scope
+            .instrument(async {
+                if !req.is_stream() {
+                    self.create_non_stream_completion(req, messages, api_key, &mut call)
+                        .await
+                } else {
+                    self.create_stream_completion(req, messages, api_key, &mut call)
+                        .await
+                }
+            })
+            .await;
+        let timed = res.map(|completion| TimedCompletion {
+            timings: call.timings(&completion),
+            completion,
+        });
+        let result = timed.as_ref().map(|timed| &timed.completion);
+        // cache hits and short-circuited requests never used the key
+        match lease {
+            Some(lease) if call.reached_groq() => lease.finish(result),
+            Some(lease) => lease.release(),
+            None => {}
+        }
+        if let (Some(ledger), Ok(completion)) = (&self.ledger, result) {
+            match completion.usage() {
+                Some(usage) if call.reached_groq() => {
+                    ledger.record(&model, user.as_deref(), &tags, usage)
+                }
+                _ => {}
+            }
+        }
+        call.finish(result);
+        timed
     }
 }
 
+/// Parses groq's error object and augments it with the HTTP status code.
+fn error_response(status: u16, body: &str) -> anyhow::Result<ErrorResponse> {
+    let mut error: ErrorResponse = serde_json::from_str(body)?;
+    error.code = reqwest::StatusCode::from_u16(status)?;
+    Ok(error)
+}
+
+/// Clients hash by the credential provider they share, the API key itself is never hashed.
 impl Hash for Groq {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.messages.hash(state);
-        self.api_key.hash(state);
+        (Arc::as_ptr(&self.credentials) as *const () as usize).hash(state);
     }
 }
 
 #[cfg(test)]
 mod completion_test {
-    use std::hash::{DefaultHasher, Hash, Hasher};
-
-    use crate::completion::{client::Groq, message::Message, request::builder};
-
-    #[test]
-    fn test_eq_and_hash() {
-        let g1 = Groq::new("api_key").add_messages(vec![Message::UserMessage {
+    use std::{
+        hash::{DefaultHasher, Hash, Hasher},
+        sync::Arc,
+    };
+
+    use crate::completion::{
+        cassette::{Cassette, Interaction, RecordedBody},
+        client::{CompletionOption, Groq},
+        credentials::EnvCredential,
+        message::Message,
+        request::builder,
+        transport::MockTransport,
+    };
+
+    fn user_message() -> Message {
+        Message::UserMessage {
             role: Some("user".to_string()),
             content: Some("Explain the importance of fast language models".to_string()),
             name: None,
             tool_call_id: None,
-        }]);
+        }
+    }
 
-        let g2 = Groq::new("api_key").add_messages(vec![Message::UserMessage {
-            role: Some("user".to_string()),
-            content: Some("Explain the importance of fast language models".to_string()),
-            name: None,
-            tool_call_id: None,
-        }]);
+    #[test]
+    fn test_eq_and_hash() {
+        let g1 = Groq::new("api_key");
+        let g2 = g1.clone();
 
         let mut hasher = DefaultHasher::new();
         let mut hasher1 = DefaultHasher::new();
@@ -231,43 +566,45 @@ mod completion_test {
         let hash_string1 = hasher1.finish();
 
         assert_eq!(hash_string, hash_string1);
+
+        let hash = |client: &Groq| {
+            let mut hasher = DefaultHasher::new();
+            client.hash(&mut hasher);
+            hasher.finish()
+        };
+        // the key is not hashed, separate clients hash apart even with the same key
+        assert_ne!(hash(&g1), hash(&Groq::new("api_key")));
+        let env = Groq::new("").with_credentials(EnvCredential::default());
+        assert_eq!(hash(&env), hash(&env.clone()));
+        assert_ne!(
+            hash(&env),
+            hash(&Groq::new("").with_credentials(EnvCredential::default()))
+        );
     }
 
     #[tokio::test]
     async fn create_completion() -> anyhow::Result<()> {
-        let messages = vec![Message::UserMessage {
-            role: Some("user".to_string()),
-            content: Some("Explain the importance of fast language models".to_string()),
-            name: None,
-            tool_call_id: None,
-        }];
+        let messages = vec![user_message()];
         let request = builder::RequestBuilder::new("mixtral-8x7b-32768".to_string());
         let api_key = env!("GROQ_API_KEY");
 
-        let mut client = Groq::new(api_key);
-        client.add_messages(messages);
+        let client = Groq::new(api_key);
 
-        let res = client.create(request).await;
+        let res = client.create(request, messages).await;
         assert!(res.is_ok());
         Ok(())
     }
 
     #[tokio::test]
     async fn create_stream_completion() -> anyhow::Result<()> {
-        let messages = vec![Message::UserMessage {
-            role: Some("user".to_string()),
-            content: Some("Explain the importance of fast language models".to_string()),
-            name: None,
-            tool_call_id: None,
-        }];
+        let messages = vec![user_message()];
         let request =
             builder::RequestBuilder::new("mixtral-8x7b-32768".to_string()).with_stream(true);
         let api_key = env!("GROQ_API_KEY");
 
-        let mut client = Groq::new(api_key);
-        client.add_messages(messages);
+        let client = Groq::new(api_key);
 
-        let res = client.create(request).await;
+        let res = client.create(request, messages).await;
         assert!(res.is_ok());
         println!("{:?}", res.unwrap());
         Ok(())
@@ -275,50 +612,89 @@ mod completion_test {
 
     #[tokio::test]
     async fn error_does_return() -> anyhow::Result<()> {
-        let messages = vec![Message::UserMessage {
-            role: Some("user".to_string()),
-            content: Some("Explain the importance of fast language models".to_string()),
-            name: None,
-            tool_call_id: None,
-        }];
+        let messages = vec![user_message()];
         let request =
             builder::RequestBuilder::new("mixtral-8x7b-32768".to_string()).with_stream(true);
         let api_key = "";
 
-        let mut client = Groq::new(api_key);
-        client.add_messages(messages);
+        let client = Groq::new(api_key);
 
-        let res = client.create(request).await;
+        let res = client.create(request, messages).await;
         assert!(res.is_err());
         eprintln!("{}", res.unwrap_err());
         Ok(())
     }
 
     #[tokio::test]
-    async fn create_with_add_tmp_message() -> anyhow::Result<()> {
-        let messages = vec![Message::SystemMessage {
-            content: Some("I am a system message".to_string()),
-            name: None,
-            role: Some("system".to_string()),
-            tool_call_id: None,
-        }];
-        let request = builder::RequestBuilder::new("mixtral-8x7b-32768".to_string());
-        let api_key = env!("GROQ_API_KEY");
+    async fn shared_client_serves_concurrent_tasks() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        for _ in 0..4 {
+            transport.push_completion("hi");
+        }
+        let client = Arc::new(Groq::new("key").with_transport(transport.clone()));
+
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    client
+                        .create(
+                            builder::RequestBuilder::new("m".into()),
+                            vec![user_message()],
+                        )
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert!(task.await?.is_ok());
+        }
+        assert_eq!(transport.requests().len(), 4);
+        Ok(())
+    }
 
-        let client = Groq::new(api_key);
-        let mut client = client;
-        client.add_messages(messages);
-        client.add_disposable_msg(Message::UserMessage {
+    #[tokio::test]
+    async fn replays_stream_from_cassette() -> anyhow::Result<()> {
+        let message = Message::UserMessage {
             role: Some("user".to_string()),
-            content: Some("Explain the importance of fast language models".to_string()),
+            content: Some("Say hi".to_string()),
             name: None,
             tool_call_id: None,
-        });
-
-        assert!(client.get_disposable_msgs().is_some());
-        let res = client.create(request).await;
-        assert!(!res.is_err());
-        assert!(client.get_disposable_msgs().is_none());
+        };
+        let request = builder::RequestBuilder::new("mixtral-8x7b-32768".to_string())
+            .with_stream(true)
+            .with_messages(vec![message.clone()])?
+            .build();
+        let chunk = r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1718000000,"model":"mixtral-8x7b-32768","system_fingerprint":null,"choices":[{"index":0,"delta":{"role":"assistant","content":"hi"},"logprobs":null,"finish_reason":null}],"x_groq":{"id":"req_1"}}"#;
+
+        let path = std::env::temp_dir().join(format!(
+            "groq_api_rs_replays_stream_{}.json",
+            std::process::id()
+        ));
+        Cassette::recording(&path).record(Interaction {
+            request: serde_json::to_value(&request)?,
+            request_headers: Vec::new(),
+            status: 200,
+            response_headers: Vec::new(),
+            body: RecordedBody::Events(vec![chunk.to_string(), "[DONE]".to_string()]),
+        })?;
+
+        let client = Groq::new("").with_cassette(Cassette::replaying(&path)?);
+        let res = client
+            .create(
+                builder::RequestBuilder::new("mixtral-8x7b-32768".to_string()).with_stream(true),
+                vec![message.clone()],
+            )
+            .await?;
+        assert!(matches!(res, CompletionOption::Stream(chunks) if chunks.len() == 1));
+
+        let res = client
+            .create(
+                builder::RequestBuilder::new("mixtral-8x7b-32768".to_string()).with_stream(true),
+                vec![message],
+            )
+            .await;
+        assert!(res.is_err());
         Ok(())
     }
 }
@@ -0,0 +1,189 @@
+use std::hash::{Hash, Hasher};
+
+use super::{
+    client::{CompletionOption, Groq},
+    message::Message,
+    request::builder::RequestBuilder,
+    timings::TimedCompletion,
+};
+
+/// Message history of a single chat, borrowing a shared [`Groq`] client.
+///
+/// The client itself is stateless and can be shared across tasks, while each conversation keeps
+/// its own messages.
+///
+/// # Private Fields
+/// - client, the client used to send the requests,
+/// - messages,  a Vec for containing messages send to the groq completion endpoint (historic messages will not clear after request)
+/// - disposable_msgs, messages that stay there for only a single request. After the request they are cleared.
+#[derive(Debug, Clone)]
+pub struct Conversation<'a> {
+    client: &'a Groq,
+    messages: Vec<Message>,
+    disposable_msgs: Vec<Message>,
+}
+
+impl<'a> Conversation<'a> {
+    pub fn new(client: &'a Groq) -> Self {
+        Self {
+            client,
+            messages: Vec::new(),
+            disposable_msgs: Vec::new(),
+        }
+    }
+
+    pub fn client(&self) -> &'a Groq {
+        self.client
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn add_message(&mut self, msg: Message) {
+        //! Non Consuming
+        //! Adds a message to the internal message vector
+        self.messages.push(msg);
+    }
+
+    pub fn add_messages(&mut self, msgs: Vec<Message>) {
+        //! Non Consuming
+        //! Add messages to the internal message vector
+        self.messages.extend(msgs);
+    }
+
+    pub fn clear_messages(&mut self) {
+        //! Non Consuming
+        //! Clears the internal message vector.
+        //! And shrink the capacity to 3.
+        self.messages.clear();
+        self.messages.shrink_to(3);
+    }
+
+    /// Clears the internal disposable_msgs vector.
+    /// # Note
+    /// Fucntion is created for internal use and is not recomended for external use.
+    pub fn clear_disposable_msgs_override(&mut self) {
+        //! Non Consuming
+        self.disposable_msgs.clear();
+    }
+
+    pub fn add_disposable_msgs(&mut self, msgs: Vec<Message>) {
+        //! Non Consuming
+        self.disposable_msgs.extend(msgs);
+    }
+
+    pub fn add_disposable_msg(&mut self, msg: Message) {
+        //! Non Consuming
+        self.disposable_msgs.push(msg);
+    }
+
+    pub fn get_disposable_msgs(&self) -> Option<Vec<Message>> {
+        if self.disposable_msgs.is_empty() {
+            None
+        } else {
+            Some(self.disposable_msgs.clone())
+        }
+    }
+
+    /// Outputs the request messages that should be passed onto the request.
+    /// Utility function created for easier logic internally.
+    /// # Returns
+    /// - Vec<Message> in the form of vec!**<global messages, disposable messages>**
+    fn get_all_request_messages(&self) -> Vec<Message> {
+        if self.disposable_msgs.is_empty() {
+            self.messages.clone()
+        } else {
+            [self.messages.clone(), self.disposable_msgs.clone()].concat()
+        }
+    }
+
+    /// Outputs the request messages that should be passed onto the request and clears the tmp messages.
+    /// Utility function created for easier logic internally.
+    fn get_request_messages_with_disposable_clear(&mut self) -> Vec<Message> {
+        let all = self.get_all_request_messages();
+        self.clear_disposable_msgs_override();
+        all
+    }
+
+    pub async fn create(&mut self, req: RequestBuilder) -> anyhow::Result<CompletionOption> {
+        //! Sends the history followed by the disposable messages, which are cleared afterwards
+        let messages = self.get_request_messages_with_disposable_clear();
+        self.client.create(req, messages).await
+    }
+
+    pub async fn create_with_timings(
+        &mut self,
+        req: RequestBuilder,
+    ) -> anyhow::Result<TimedCompletion> {
+        //! Same as [`Conversation::create`], additionally returning the client side timings
+        let messages = self.get_request_messages_with_disposable_clear();
+        self.client.create_with_timings(req, messages).await
+    }
+}
+
+impl Hash for Conversation<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.messages.hash(state);
+        self.client.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod conversation_test {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use crate::completion::{client::Groq, message::Message, request::builder};
+
+    fn user_message() -> Message {
+        Message::UserMessage {
+            role: Some("user".to_string()),
+            content: Some("Explain the importance of fast language models".to_string()),
+            name: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_eq_and_hash() {
+        let client = Groq::new("api_key");
+        let mut c1 = client.conversation();
+        c1.add_messages(vec![user_message()]);
+
+        let mut c2 = client.conversation();
+        c2.add_messages(vec![user_message()]);
+
+        let mut hasher = DefaultHasher::new();
+        let mut hasher1 = DefaultHasher::new();
+
+        c1.hash(&mut hasher);
+        c2.hash(&mut hasher1);
+        let hash_string = hasher.finish();
+        let hash_string1 = hasher1.finish();
+
+        assert_eq!(hash_string, hash_string1);
+    }
+
+    #[tokio::test]
+    async fn create_with_add_tmp_message() -> anyhow::Result<()> {
+        let messages = vec![Message::SystemMessage {
+            content: Some("I am a system message".to_string()),
+            name: None,
+            role: Some("system".to_string()),
+            tool_call_id: None,
+        }];
+        let request = builder::RequestBuilder::new("mixtral-8x7b-32768".to_string());
+        let api_key = env!("GROQ_API_KEY");
+
+        let client = Groq::new(api_key);
+        let mut conversation = client.conversation();
+        conversation.add_messages(messages);
+        conversation.add_disposable_msg(user_message());
+
+        assert!(conversation.get_disposable_msgs().is_some());
+        let res = conversation.create(request).await;
+        assert!(res.is_ok());
+        assert!(conversation.get_disposable_msgs().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+//! Where the client gets its API key from, see [`CredentialProvider`].
+use std::{
+    ffi::OsString,
+    fmt::Debug,
+    future::Future,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use tokio::sync::Mutex;
+use zeroize::Zeroize;
+
+/// A secret value such as an API key.
+///
+/// The value is redacted in `Debug` and `Display`, wiped from memory when dropped, and `Secret`
+/// deliberately implements neither `Hash` nor `Serialize`. Use [`Secret::expose`] to read it.
+#[derive(Clone, Default)]
+pub struct Secret {
+    value: String,
+}
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+        }
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "***")
+    }
+}
+
+/// Source of the API key, asked before every request, installed with
+/// [`Groq::with_credentials`](super::client::Groq::with_credentials).
+///
+/// Implementations must keep the secret out of their `Debug` output.
+/// - [`StaticCredential`], a fixed key, what [`Groq::new`](super::client::Groq::new) uses
+/// - [`EnvCredential`], an environment variable
+/// - [`FileCredential`], the content of a file, e.g. a mounted secret
+/// - [`CommandCredential`], the output of a command, e.g. a password manager CLI
+/// - [`FnCredential`], an async closure, e.g. a call to a secret store
+/// - [`RefreshingCredential`], caches another provider for a while
+pub trait CredentialProvider: Debug + Send + Sync {
+    fn api_key(&self) -> BoxFuture<'_, anyhow::Result<Secret>>;
+}
+
+/// A fixed API key.
+#[derive(Debug, Clone)]
+pub struct StaticCredential(pub Secret);
+
+impl CredentialProvider for StaticCredential {
+    fn api_key(&self) -> BoxFuture<'_, anyhow::Result<Secret>> {
+        Box::pin(async { Ok(self.0.clone()) })
+    }
+}
+
+/// Reads the API key from an environment variable on every request.
+#[derive(Debug, Clone)]
+pub struct EnvCredential {
+    pub var: String,
+}
+
+impl EnvCredential {
+    pub fn new(var: &str) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+impl Default for EnvCredential {
+    fn default() -> Self {
+        Self::new("GROQ_API_KEY")
+    }
+}
+
+impl CredentialProvider for EnvCredential {
+    fn api_key(&self) -> BoxFuture<'_, anyhow::Result<Secret>> {
+        Box::pin(async {
+            let value = std::env::var(&self.var)
+                .map_err(|err| anyhow::anyhow!("cannot read ${}: {}", self.var, err))?;
+            Ok(Secret::new(value))
+        })
+    }
+}
+
+/// Reads the API key from a file on every request, surrounding whitespace is trimmed.
+#[derive(Debug, Clone)]
+pub struct FileCredential {
+    pub path: PathBuf,
+}
+
+impl FileCredential {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CredentialProvider for FileCredential {
+    fn api_key(&self) -> BoxFuture<'_, anyhow::Result<Secret>> {
+        Box::pin(async {
+            let mut content = tokio::fs::read_to_string(&self.path)
+                .await
+                .map_err(|err| anyhow::anyhow!("cannot read {}: {}", self.path.display(), err))?;
+            let secret = Secret::new(content.trim());
+            content.zeroize();
+            Ok(secret)
+        })
+    }
+}
+
+/// Runs a command and takes its standard output, trimmed, as the API key.
+/// Usually wrapped in a [`RefreshingCredential`] so the command does not run for every request.
+/// ```ignore no_run
+/// let provider = RefreshingCredential::new(
+///     CommandCredential::new("op", ["read", "op://dev/groq/api-key"]),
+///     Duration::from_secs(3600),
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandCredential {
+    pub program: OsString,
+    pub args: Vec<OsString>,
+}
+
+impl CommandCredential {
+    pub fn new(
+        program: impl Into<OsString>,
+        args: impl IntoIterator<Item = impl Into<OsString>>,
+    ) -> Self {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl CredentialProvider for CommandCredential {
+    fn api_key(&self) -> BoxFuture<'_, anyhow::Result<Secret>> {
+        Box::pin(async {
+            let mut output = tokio::process::Command::new(&self.program)
+                .args(&self.args)
+                .kill_on_drop(true)
+                .output()
+                .await?;
+            anyhow::ensure!(
+                output.status.success(),
+                "credential command {:?} failed with {}",
+                self.program,
+                output.status
+            );
+            let secret = Secret::new(String::from_utf8_lossy(&output.stdout).trim());
+            output.stdout.zeroize();
+            Ok(secret)
+        })
+    }
+}
+
+type FetchFn = dyn Fn() -> BoxFuture<'static, anyhow::Result<Secret>> + Send + Sync;
+
+/// Gets the API key from an async closure.
+/// ```ignore no_run
+/// let provider = FnCredential::new(move || {
+///     let vault = vault.clone();
+///     async move { Ok(Secret::new(vault.read("groq").await?)) }
+/// });
+/// ```
+#[derive(Clone)]
+pub struct FnCredential {
+    fetch: Arc<FetchFn>,
+}
+
+impl FnCredential {
+    pub fn new<F, Fut>(fetch: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<Secret>> + Send + 'static,
+    {
+        Self {
+            fetch: Arc::new(move || Box::pin(fetch())),
+        }
+    }
+}
+
+impl Debug for FnCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnCredential").finish_non_exhaustive()
+    }
+}
+
+impl CredentialProvider for FnCredential {
+    fn api_key(&self) -> BoxFuture<'_, anyhow::Result<Secret>> {
+        (self.fetch)()
+    }
+}
+
+/// Caches the key of another provider for `ttl`, concurrent requests share a single refresh.
+/// A failed refresh is returned to the caller and retried by the next request.
+pub struct RefreshingCredential<P> {
+    inner: P,
+    ttl: Duration,
+    cached: Mutex<Option<(Secret, Instant)>>,
+}
+
+impl<P: CredentialProvider> RefreshingCredential<P> {
+    pub fn new(inner: P, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub async fn invalidate(&self) {
+        //! Forgets the cached key, e.g. after the API rejected it
+        *self.cached.lock().await = None;
+    }
+}
+
+impl<P: Debug> Debug for RefreshingCredential<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the cache is left out so that the output stays the same across refreshes
+        f.debug_struct("RefreshingCredential")
+            .field("inner", &self.inner)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P: CredentialProvider> CredentialProvider for RefreshingCredential<P> {
+    fn api_key(&self) -> BoxFuture<'_, anyhow::Result<Secret>> {
+        Box::pin(async {
+            let mut cached = self.cached.lock().await;
+            match &*cached {
+                Some((secret, fetched)) if fetched.elapsed() < self.ttl => Ok(secret.clone()),
+                _ => {
+                    let secret = self.inner.api_key().await?;
+                    *cached = Some((secret.clone(), Instant::now()));
+                    Ok(secret)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod credentials_test {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{
+        CommandCredential, CredentialProvider, EnvCredential, FileCredential, FnCredential,
+        RefreshingCredential, Secret,
+    };
+    use crate::completion::{
+        client::Groq,
+        request::builder::RequestBuilder,
+        transport::{fixtures, MockTransport},
+    };
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secret = Secret::new("gsk_live_123");
+        assert_eq!(format!("{:?} {}", secret, secret), "Secret(***) ***");
+        assert_eq!(secret.expose(), "gsk_live_123");
+
+        let client = Groq::new("gsk_live_123");
+        assert!(!format!("{:?}", client).contains("gsk_live_123"));
+    }
+
+    #[tokio::test]
+    async fn providers_read_their_source() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("groq-key-{}", std::process::id()));
+        std::fs::write(&path, "gsk_file\n")?;
+        assert_eq!(
+            FileCredential::new(&path).api_key().await?.expose(),
+            "gsk_file"
+        );
+        std::fs::remove_file(&path)?;
+
+        let echo = CommandCredential::new("echo", ["gsk_command"]);
+        assert_eq!(echo.api_key().await?.expose(), "gsk_command");
+        assert!(CommandCredential::new("false", Vec::<String>::new())
+            .api_key()
+            .await
+            .is_err());
+        assert!(EnvCredential::new("GROQ_TEST_UNSET_VARIABLE")
+            .api_key()
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refreshing_provider_caches_and_feeds_requests() -> anyhow::Result<()> {
+        let fetches = Arc::new(AtomicU32::new(0));
+        let counter = fetches.clone();
+        let provider = RefreshingCredential::new(
+            FnCredential::new(move || {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                async move { Ok(Secret::new(format!("gsk_{}", n))) }
+            }),
+            Duration::from_secs(60),
+        );
+        assert_eq!(provider.api_key().await?.expose(), "gsk_0");
+        assert_eq!(provider.api_key().await?.expose(), "gsk_0");
+        provider.invalidate().await;
+        assert_eq!(provider.api_key().await?.expose(), "gsk_1");
+
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(401, r#"{"error":{"message":"no","type":"auth"}}"#);
+        let client = Groq::new("")
+            .with_transport(transport.clone())
+            .with_credentials(provider);
+        let messages = vec![fixtures::user("hello")];
+        let _ = client
+            .create(RequestBuilder::new("m".into()), messages)
+            .await;
+        let headers = &transport.requests()[0].headers;
+        assert!(headers.contains(&("authorization".into(), "Bearer gsk_1".into())));
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+//! Model fallback chains, retrying the same messages on the next model when a model is
+//! decommissioned, overloaded or cannot fit the prompt.
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client::{CompletionOption, Groq},
+    message::Message,
+    request::builder::{BuilderConfig, RequestBuilder},
+    response::ErrorResponse,
+    timings::ClientTimings,
+};
+
+/// Class of a failed completion call, used to decide whether to fall back.
+/// - Decommissioned, the model was retired or does not exist (`model_decommissioned`,
+///   `model_not_found`, 404)
+/// - Overloaded, groq is over capacity for the model (503)
+/// - ContextOverflow, the prompt and completion do not fit the context window of the model
+///   (`context_length_exceeded`, 413)
+/// - RateLimited, too many requests or tokens for the model (429)
+/// - ServerError, any other 5xx answer
+/// - Transport, no answer at all (connection reset, timeouts, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    Decommissioned,
+    Overloaded,
+    ContextOverflow,
+    RateLimited,
+    ServerError,
+    Transport,
+}
+
+impl FailureClass {
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        //! Classifies an error returned by [`Groq::create`], None for errors no other model can fix
+        //! (invalid requests, authentication, interrupted streams, ...)
+        let Some(res) = err.downcast_ref::<ErrorResponse>() else {
+            return err
+                .downcast_ref::<reqwest::Error>()
+                .map(|_| FailureClass::Transport);
+        };
+        let code = res.error.code.as_deref().unwrap_or_default();
+        let class = match (res.code.as_u16(), code) {
+            (_, "model_decommissioned" | "model_not_found") | (404, _) => {
+                FailureClass::Decommissioned
+            }
+            (_, "context_length_exceeded") | (413, _) => FailureClass::ContextOverflow,
+            (503, _) => FailureClass::Overloaded,
+            (429, _) => FailureClass::RateLimited,
+            (status, _) if (500..600).contains(&status) => FailureClass::ServerError,
+            _ => return None,
+        };
+        Some(class)
+    }
+}
+
+impl Display for FailureClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FailureClass::Decommissioned => "decommissioned",
+            FailureClass::Overloaded => "overloaded",
+            FailureClass::ContextOverflow => "context overflow",
+            FailureClass::RateLimited => "rate limited",
+            FailureClass::ServerError => "server error",
+            FailureClass::Transport => "transport error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A model of a fallback chain.
+/// - model, the model to try
+/// - overrides, request parameters layered on the original request for this model, e.g. a
+///   smaller `max_tokens` for a model with a smaller context window
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FallbackModel {
+    pub model: String,
+    #[serde(default)]
+    pub overrides: BuilderConfig,
+}
+
+/// Models tried in order after the model of the request, installed with
+/// [`RequestBuilder::with_fallback`].
+/// ```ignore no_run
+/// let req = RequestBuilder::new("llama-3.3-70b-versatile".into()).with_fallback(
+///     FallbackPolicy::new()
+///         .with_model("llama-3.1-70b-versatile")
+///         .with_model_overrides("llama3-8b-8192", BuilderConfig {
+///             max_tokens: Some(1024),
+///             ..Default::default()
+///         }),
+/// );
+/// let served = client.create_with_fallback(req, messages).await?;
+/// println!("answered by {}", served.model);
+/// ```
+/// - models, the fallback models, in order
+/// - on, the failures moving on to the next model, decommissioned, overloaded and context overflow
+///   by default
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FallbackPolicy {
+    pub models: Vec<FallbackModel>,
+    pub on: Vec<FailureClass>,
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        Self {
+            models: Vec::new(),
+            on: vec![
+                FailureClass::Decommissioned,
+                FailureClass::Overloaded,
+                FailureClass::ContextOverflow,
+            ],
+        }
+    }
+}
+
+impl FallbackPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model(self, model: &str) -> Self {
+        self.with_model_overrides(model, BuilderConfig::default())
+    }
+
+    pub fn with_model_overrides(mut self, model: &str, overrides: BuilderConfig) -> Self {
+        self.models.push(FallbackModel {
+            model: model.into(),
+            overrides,
+        });
+        self
+    }
+
+    pub fn with_failures(mut self, on: Vec<FailureClass>) -> Self {
+        //! Replaces the failures moving on to the next model
+        self.on = on;
+        self
+    }
+}
+
+/// A model of the chain that failed before another one answered.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FailedAttempt {
+    pub model: String,
+    pub class: FailureClass,
+    pub error: String,
+}
+
+/// Completion returned by [`Groq::create_with_fallback`].
+/// - completion, the completion of the model that answered
+/// - model, the model that answered
+/// - failed, the models tried before it, in order
+#[derive(Debug, Clone)]
+pub struct FallbackCompletion {
+    pub completion: CompletionOption,
+    pub model: String,
+    pub failed: Vec<FailedAttempt>,
+}
+
+impl FallbackCompletion {
+    pub fn fell_back(&self) -> bool {
+        !self.failed.is_empty()
+    }
+}
+
+impl Groq {
+    pub async fn create_with_fallback(
+        &self,
+        req: RequestBuilder,
+        messages: Vec<Message>,
+    ) -> anyhow::Result<FallbackCompletion> {
+        //! Sends `messages` to the model of `req`, then to every model of its [`FallbackPolicy`]
+        //! in order while the calls fail with one of the failures of the policy.
+        //! Returns the error of the last model tried when the chain is exhausted, or the first
+        //! error the policy does not fall back on.
+        self.fallback_chain(req, messages, 0)
+            .await
+            .map(|(served, _)| served)
+    }
+
+    /// [`Groq::create_with_fallback`] as the given retry of a call, every model of the chain
+    /// reports `retries` on its span. The timings are those of the call of the model that answered.
+    pub(crate) async fn fallback_chain(
+        &self,
+        req: RequestBuilder,
+        messages: Vec<Message>,
+        retries: u32,
+    ) -> anyhow::Result<(FallbackCompletion, ClientTimings)> {
+        let policy = req.fallback().cloned().unwrap_or_default();
+        let req = req.without_fallback();
+        let mut fallbacks = policy.models.iter().map(|fallback| {
+            req.clone().with_overrides(
+                fallback
+                    .overrides
+                    .clone()
+                    .merge(BuilderConfig::new(&fallback.model)),
+            )
+        });
+
+        let mut failed = Vec::new();
+        let mut candidate = req.clone();
+        loop {
+            let model = candidate.model().to_string();
+            let err = match self
+                .create_timed(candidate, messages.clone(), retries)
+                .await
+            {
+                Ok(timed) => {
+                    let served = FallbackCompletion {
+                        completion: timed.completion,
+                        model,
+                        failed,
+                    };
+                    return Ok((served, timed.timings));
+                }
+                Err(err) => err,
+            };
+            let class = match FailureClass::of(&err) {
+                Some(class) if policy.on.contains(&class) => class,
+                _ => return Err(err),
+            };
+            candidate = match fallbacks.next() {
+                Some(next) => next,
+                None => return Err(err),
+            };
+            failed.push(FailedAttempt {
+                model,
+                class,
+                error: err.to_string(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod fallback_test {
+    use std::sync::Arc;
+
+    use super::{FailureClass, FallbackPolicy};
+    use crate::completion::{
+        client::Groq,
+        request::builder::{BuilderConfig, RequestBuilder},
+        response::ErrorResponse,
+        transport::{fixtures, MockTransport},
+    };
+
+    fn error(code: &str) -> String {
+        format!(
+            r#"{{"error":{{"message":"failed","type":"invalid_request_error","code":"{}"}}}}"#,
+            code
+        )
+    }
+
+    fn policy() -> FallbackPolicy {
+        FallbackPolicy::new()
+            .with_model("llama-3.1-70b-versatile")
+            .with_model_overrides(
+                "llama3-8b-8192",
+                BuilderConfig {
+                    max_tokens: Some(512),
+                    ..Default::default()
+                },
+            )
+    }
+
+    #[tokio::test]
+    async fn falls_back_in_order_with_overrides() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport
+            .push_json(400, error("model_decommissioned"))
+            .push_json(503, error("service_unavailable"))
+            .push_completion("hi");
+        let client = Groq::new("key").with_transport(transport.clone());
+
+        let req = RequestBuilder::new("mixtral-8x7b-32768".into())
+            .with_temperature(0.3)
+            .with_fallback(policy());
+        let served = client
+            .create_with_fallback(req, vec![fixtures::user("hello")])
+            .await?;
+        assert_eq!(served.model, "llama3-8b-8192");
+        let classes: Vec<_> = served.failed.iter().map(|failed| failed.class).collect();
+        assert_eq!(
+            classes,
+            vec![FailureClass::Decommissioned, FailureClass::Overloaded]
+        );
+
+        let sent: Vec<_> = transport
+            .requests()
+            .into_iter()
+            .map(|mut req| req.body.take())
+            .collect();
+        assert_eq!(sent[0]["model"], "mixtral-8x7b-32768");
+        assert_eq!(sent[1]["model"], "llama-3.1-70b-versatile");
+        assert_eq!(sent[2]["model"], "llama3-8b-8192");
+        assert_eq!(sent[2]["max_tokens"], 512);
+        assert!((sent[2]["temperature"].as_f64().unwrap() - 0.3).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_stops_on_other_errors_and_exhaustion() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(401, error("invalid_api_key"));
+        let client = Groq::new("key").with_transport(transport.clone());
+        let req = RequestBuilder::new("m".into()).with_fallback(policy());
+        let err = client
+            .create(req.clone(), vec![fixtures::user("hello")])
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<ErrorResponse>().unwrap().code, 401);
+        assert_eq!(transport.requests().len(), 1);
+
+        for _ in 0..3 {
+            transport.push_json(400, error("context_length_exceeded"));
+        }
+        let err = client
+            .create(req, vec![fixtures::user("hello")])
+            .await
+            .unwrap_err();
+        assert_eq!(FailureClass::of(&err), Some(FailureClass::ContextOverflow));
+        assert_eq!(transport.requests().len(), 4);
+    }
+}
@@ -0,0 +1,190 @@
+//! Canonical content fingerprints of requests and messages.
+//!
+//! The canonical form is the json of a value with the keys of every object sorted and every
+//! float written as the bit pattern of its `f64` value, so that two values have the same
+//! canonical form exactly when they would send the same content to groq. The `Hash` and
+//! `PartialEq` impls of [`Request`], [`RequestBuilder`], [`BuilderConfig`] and [`Message`] are
+//! derived from it, which keeps them consistent with each other and with [`Fingerprint::fingerprint`].
+use std::fmt::Write;
+
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::{
+    message::Message,
+    request::{
+        builder::{BuilderConfig, RequestBuilder},
+        Request,
+    },
+};
+
+/// Stable content fingerprint, identical across processes, platforms and crate versions as long
+/// as the wire format of the value does not change.
+pub trait Fingerprint {
+    fn canonical_json(&self) -> String;
+
+    fn fingerprint(&self) -> String {
+        //! Hex encoded sha256 of [`Fingerprint::canonical_json`]
+        let digest = Sha256::digest(self.canonical_json().as_bytes());
+        digest.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+    }
+}
+
+impl Fingerprint for Value {
+    fn canonical_json(&self) -> String {
+        let mut out = String::new();
+        write_canonical(self, &mut out);
+        out
+    }
+}
+
+/// Canonical json of any serializable value, `null` when it cannot be represented as json.
+pub(crate) fn canonical<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_value(value)
+        .unwrap_or(Value::Null)
+        .canonical_json()
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) | Value::String(_) => out.push_str(&value.to_string()),
+        Value::Number(number) => match (number.as_u64(), number.as_i64(), number.as_f64()) {
+            (Some(n), _, _) => out.push_str(&n.to_string()),
+            (_, Some(n), _) => out.push_str(&n.to_string()),
+            (_, _, Some(n)) => {
+                let _ = write!(out, "\"f64:{:016x}\"", n.to_bits());
+            }
+            _ => out.push_str(&number.to_string()),
+        },
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+impl Fingerprint for Request {
+    fn canonical_json(&self) -> String {
+        canonical(self)
+    }
+}
+
+impl Fingerprint for RequestBuilder {
+    fn canonical_json(&self) -> String {
+        //! The client side options (timeouts, cache mode, ...) are not part of the content
+        canonical(&self.clone().into_request())
+    }
+}
+
+impl Fingerprint for BuilderConfig {
+    fn canonical_json(&self) -> String {
+        canonical(self)
+    }
+}
+
+impl Fingerprint for Message {
+    fn canonical_json(&self) -> String {
+        canonical(self)
+    }
+}
+
+/// Implements `Hash`, `PartialEq` and `Eq` from [`Fingerprint::canonical_json`].
+macro_rules! canonical_identity {
+    ($($ty:ty),*) => {$(
+        impl std::hash::Hash for $ty {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.canonical_json().hash(state);
+            }
+        }
+
+        impl PartialEq for $ty {
+            fn eq(&self, other: &Self) -> bool {
+                self.canonical_json() == other.canonical_json()
+            }
+        }
+
+        impl Eq for $ty {}
+    )*};
+}
+
+canonical_identity!(Request, RequestBuilder, BuilderConfig, Message);
+
+#[cfg(test)]
+mod fingerprint_test {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use serde_json::json;
+
+    use super::Fingerprint;
+    use crate::completion::request::{builder::RequestBuilder, Function, Tool};
+
+    fn hash(value: &impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn floats_are_told_apart() {
+        let cold = RequestBuilder::new("m".into()).with_temperature(0.2);
+        let warm = RequestBuilder::new("m".into()).with_temperature(0.7);
+        assert_ne!(cold, warm);
+        assert_ne!(hash(&cold), hash(&warm));
+        assert_ne!(cold.fingerprint(), warm.fingerprint());
+        assert_eq!(
+            cold.fingerprint(),
+            RequestBuilder::new("m".into())
+                .with_temperature(0.2)
+                .fingerprint()
+        );
+    }
+
+    #[test]
+    fn function_parameters_are_hashed() {
+        let tool = |parameters| Tool {
+            tool_type: "function".into(),
+            function: Function {
+                description: None,
+                name: Some("weather".into()),
+                parameters: Some(parameters),
+            },
+        };
+        let a = RequestBuilder::new("m".into()).with_tools(vec![tool(json!({"a": 1}))]);
+        let b = RequestBuilder::new("m".into()).with_tools(vec![tool(json!({"b": 1}))]);
+        assert_ne!(a, b);
+        assert_ne!(hash(&a), hash(&b));
+        assert_ne!(hash(&tool(json!({"a": 1}))), hash(&tool(json!({"b": 1}))));
+    }
+
+    #[test]
+    fn key_order_does_not_matter() {
+        let a = json!({"a": 1, "b": [0.5, {"d": true, "c": null}]});
+        let b = json!({"b": [0.5, {"c": null, "d": true}], "a": 1});
+        assert_eq!(a.canonical_json(), b.canonical_json());
+        assert_eq!(a.fingerprint(), b.fingerprint());
+    }
+}
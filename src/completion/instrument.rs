@@ -0,0 +1,156 @@
+//! Crate internal bookkeeping of a single completion call.
+//! With the `tracing` feature enabled every call gets a `groq.completion` span and with the
+//! `opentelemetry` feature the call is reported through [`telemetry`](super::telemetry), without
+//! either all of this compiles down to a couple of timestamps.
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use super::{
+    client::CompletionOption, request::builder::RequestBuilder, response::UsageInfo,
+    timings::ClientTimings,
+};
+
+pub(crate) struct CallSpan {
+    started: Instant,
+    /// arrival of every stream chunk carrying tokens, relative to `started`
+    token_arrivals: Vec<Duration>,
+    status: Option<u16>,
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    retries: u32,
+    #[cfg(feature = "opentelemetry")]
+    otel: super::telemetry::OtelCall,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+/// Handle used to run the future of a call inside its span.
+pub(crate) struct CallScope {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl CallScope {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn instrument<F: Future>(self, fut: F) -> impl Future<Output = F::Output> {
+        tracing::Instrument::instrument(fut, self.span)
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn instrument<F: Future>(self, fut: F) -> impl Future<Output = F::Output> {
+        fut
+    }
+}
+
+impl CallSpan {
+    pub(crate) fn start(req: &RequestBuilder, retries: u32) -> Self {
+        //! `retries` is the number of calls made before this one by a retry loop
+        #[cfg(not(any(feature = "tracing", feature = "opentelemetry")))]
+        let _ = req;
+        Self {
+            started: Instant::now(),
+            token_arrivals: Vec::new(),
+            status: None,
+            retries,
+            #[cfg(feature = "opentelemetry")]
+            otel: super::telemetry::OtelCall::start(req),
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "groq.completion",
+                model = req.model(),
+                stream = req.is_stream(),
+                status_code = tracing::field::Empty,
+                prompt_tokens = tracing::field::Empty,
+                completion_tokens = tracing::field::Empty,
+                total_tokens = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                time_to_first_token_ms = tracing::field::Empty,
+                retries = tracing::field::Empty,
+            ),
+        }
+    }
+
+    pub(crate) fn scope(&self) -> CallScope {
+        CallScope {
+            #[cfg(feature = "tracing")]
+            span: self.span.clone(),
+        }
+    }
+
+    pub(crate) fn status(&mut self, status: u16) {
+        self.status = Some(status);
+        #[cfg(feature = "tracing")]
+        self.span.record("status_code", status);
+    }
+
+    pub(crate) fn reached_groq(&self) -> bool {
+        //! Whether an answer came from groq, false for cache hits and short-circuited requests
+        self.status.is_some()
+    }
+
+    pub(crate) fn stream_opened(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(parent: &self.span, "event stream opened");
+    }
+
+    pub(crate) fn cache_failed(&self, err: &anyhow::Error) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(parent: &self.span, error = %err, "response cache failed");
+        #[cfg(not(feature = "tracing"))]
+        let _ = err;
+    }
+
+    pub(crate) fn retried(&mut self) {
+        self.retries += 1;
+    }
+
+    pub(crate) fn tokens_received(&mut self) {
+        self.token_arrivals.push(self.started.elapsed());
+    }
+
+    pub(crate) fn timings(&self, completion: &CompletionOption) -> ClientTimings {
+        ClientTimings::measure(self.started.elapsed(), &self.token_arrivals, completion)
+    }
+
+    pub(crate) fn usage(&self, usage: &UsageInfo) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("prompt_tokens", usage.prompt_tokens);
+            self.span
+                .record("completion_tokens", usage.completion_tokens);
+            self.span.record("total_tokens", usage.total_tokens);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = usage;
+    }
+
+    pub(crate) fn finish(self, result: Result<&CompletionOption, &anyhow::Error>) {
+        if let Some(usage) = result.ok().and_then(CompletionOption::usage) {
+            self.usage(usage);
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            let latency = self.started.elapsed();
+            self.span.record("latency_ms", latency.as_millis() as u64);
+            self.span.record("retries", self.retries);
+            if let Some(ttft) = self.token_arrivals.first() {
+                self.span
+                    .record("time_to_first_token_ms", ttft.as_millis() as u64);
+            }
+            match result {
+                Ok(_) => tracing::debug!(parent: &self.span, "completion finished"),
+                Err(err) => tracing::warn!(parent: &self.span, error = %err, "completion failed"),
+            }
+        }
+
+        #[cfg(feature = "opentelemetry")]
+        self.otel.finish(
+            self.started.elapsed(),
+            self.token_arrivals.first().copied(),
+            self.status,
+            result,
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::Debug;
+
+use futures::future::BoxFuture;
+
+use super::{
+    client::CompletionOption, request::Request, response::StreamResponse, transport::HttpResponse,
+};
+
+/// A layer of the interceptor chain installed with
+/// [`Groq::with_interceptor`](super::client::Groq::with_interceptor).
+///
+/// # Order
+/// - `on_request` runs in the order the interceptors were added.
+/// - `on_response`, `on_chunk` and `on_completion` run in reverse order, so the first interceptor
+///   added is the outermost layer.
+///
+/// Every hook may fail, which aborts the completion with that error.
+/// All hooks default to a no-op so an interceptor only implements what it needs.
+pub trait Interceptor: Debug + Send + Sync {
+    /// Sees the built request and the outgoing HTTP headers (including `authorization`) before
+    /// they leave the process.
+    /// Returning `Some` short-circuits the chain: the remaining interceptors and the transport
+    /// are skipped and the returned completion is handed to the caller as is.
+    fn on_request<'a>(
+        &'a self,
+        _req: &'a mut Request,
+        _headers: &'a mut Vec<(String, String)>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<CompletionOption>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Sees the raw buffered response before it is parsed.
+    /// Called for non stream completions and for rejected stream requests.
+    fn on_response<'a>(
+        &'a self,
+        _req: &'a Request,
+        _res: &'a mut HttpResponse,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Sees every parsed chunk of a stream completion, in the order they were received.
+    fn on_chunk(&self, _req: &Request, _chunk: &mut StreamResponse) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Sees the final completion before it is returned to the caller.
+    fn on_completion<'a>(
+        &'a self,
+        _req: &'a Request,
+        _completion: &'a mut CompletionOption,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Interceptor adding a fixed set of headers to every request.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderInterceptor {
+    headers: Vec<(String, String)>,
+}
+
+impl HeaderInterceptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl Interceptor for HeaderInterceptor {
+    fn on_request<'a>(
+        &'a self,
+        _req: &'a mut Request,
+        headers: &'a mut Vec<(String, String)>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<CompletionOption>>> {
+        headers.extend(self.headers.iter().cloned());
+        Box::pin(async { Ok(None) })
+    }
+}
+
+#[cfg(test)]
+mod interceptor_test {
+    use std::sync::Arc;
+
+    use futures::future::BoxFuture;
+
+    use super::{HeaderInterceptor, Interceptor};
+    use crate::completion::{
+        client::{CompletionOption, Groq},
+        message::Message,
+        request::{builder::RequestBuilder, Request},
+        response::StreamResponse,
+        transport::MockTransport,
+    };
+
+    #[derive(Debug)]
+    struct Redactor;
+
+    impl Interceptor for Redactor {
+        fn on_request<'a>(
+            &'a self,
+            req: &'a mut Request,
+            _headers: &'a mut Vec<(String, String)>,
+        ) -> BoxFuture<'a, anyhow::Result<Option<CompletionOption>>> {
+            for msg in req.messages_mut() {
+                if let Some(content) = msg.content_mut() {
+                    *content = content.replace("hunter2", "[PII]");
+                }
+            }
+            Box::pin(async { Ok(None) })
+        }
+
+        fn on_chunk(&self, _req: &Request, chunk: &mut StreamResponse) -> anyhow::Result<()> {
+            chunk.model = "rewritten".into();
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct Blocker;
+
+    impl Interceptor for Blocker {
+        fn on_request<'a>(
+            &'a self,
+            _req: &'a mut Request,
+            _headers: &'a mut Vec<(String, String)>,
+        ) -> BoxFuture<'a, anyhow::Result<Option<CompletionOption>>> {
+            Box::pin(async { Ok(Some(CompletionOption::Stream(Vec::new()))) })
+        }
+    }
+
+    fn user_message(content: &str) -> Message {
+        Message::UserMessage {
+            role: Some("user".to_string()),
+            content: Some(content.to_string()),
+            name: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn chain_mutates_request_headers_and_chunks() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_events(vec![
+            r#"{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{"index":0,"delta":{"content":"ok"},"logprobs":null,"finish_reason":null}],"x_groq":null}"#.into(),
+            "[DONE]".into(),
+        ]);
+        let client = Groq::new("key")
+            .with_transport(transport.clone())
+            .with_interceptor(HeaderInterceptor::new().with_header("x-tenant", "acme"))
+            .with_interceptor(Redactor);
+
+        let res = client
+            .create(
+                RequestBuilder::new("m".into()).with_stream(true),
+                vec![user_message("my password is hunter2")],
+            )
+            .await?;
+        assert!(matches!(res, CompletionOption::Stream(chunks) if chunks[0].model == "rewritten"));
+
+        let sent = &transport.requests()[0];
+        assert!(sent.headers.contains(&("x-tenant".into(), "acme".into())));
+        assert_eq!(sent.body["messages"][0]["content"], "my password is [PII]");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn short_circuit_skips_transport() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        let client = Groq::new("key")
+            .with_transport(transport.clone())
+            .with_interceptor(Blocker);
+
+        let res = client
+            .create(RequestBuilder::new("m".into()), vec![user_message("hi")])
+            .await?;
+        assert!(matches!(res, CompletionOption::Stream(chunks) if chunks.is_empty()));
+        assert!(transport.requests().is_empty());
+        Ok(())
+    }
+}
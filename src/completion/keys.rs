@@ -0,0 +1,405 @@
+//! Pool of API keys a client spreads its requests over, see [`KeyPool`].
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{client::CompletionOption, credentials::Secret, response::ErrorResponse};
+
+/// How [`KeyPool`] picks the key of a request among the keys that are not quarantined.
+/// - RoundRobin, every key in turn
+/// - LeastRecentlyRateLimited, the key whose last 429 is the oldest, keys never rate limited first
+/// - Weighted, round robin where every key gets a share of the requests proportional to its weight
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum KeySelection {
+    #[default]
+    RoundRobin,
+    LeastRecentlyRateLimited,
+    Weighted,
+}
+
+/// Requests sent with a key of the pool.
+/// - requests, every request sent with the key
+/// - failures, the requests that failed, whatever the reason
+/// - rate_limited, the requests answered with 429
+/// - unauthorized, the requests answered with 401
+/// - prompt_tokens / completion_tokens, the tokens reported by groq for the successful requests
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct KeyUsage {
+    pub requests: u64,
+    pub failures: u64,
+    pub rate_limited: u64,
+    pub unauthorized: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Snapshot of a key of the pool returned by [`KeyPool::status`], the key itself is never exposed.
+/// - quarantined_for, how long the key is still left out of the selection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyStatus {
+    pub label: String,
+    pub weight: u32,
+    pub usage: KeyUsage,
+    pub quarantined_for: Option<Duration>,
+    pub last_rate_limited: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Health {
+    quarantined_until: Option<Instant>,
+    last_rate_limited: Option<Instant>,
+    usage: KeyUsage,
+}
+
+impl Health {
+    fn is_available(&self, now: Instant) -> bool {
+        !matches!(self.quarantined_until, Some(until) if until > now)
+    }
+}
+
+struct PooledKey {
+    label: String,
+    key: Secret,
+    weight: u32,
+    health: Mutex<Health>,
+}
+
+/// Several API keys (e.g. of different projects) shared by a client, installed with
+/// [`Groq::with_key_pool`](super::client::Groq::with_key_pool).
+///
+/// Every request is sent with a key picked according to the [`KeySelection`]. Keys answered with
+/// 429 are quarantined for `rate_limit_quarantine` (30s by default) and keys answered with 401 for
+/// `auth_quarantine` (10 minutes by default). When every key is quarantined the key released the
+/// soonest is used rather than failing the request.
+/// ```ignore no_run
+/// let pool = KeyPool::new()
+///     .with_labeled_key("team-a", &key_a, 2)
+///     .with_labeled_key("team-b", &key_b, 1)
+///     .with_selection(KeySelection::Weighted);
+/// let client = Groq::new("").with_key_pool(pool);
+/// ```
+pub struct KeyPool {
+    keys: Vec<PooledKey>,
+    selection: KeySelection,
+    rate_limit_quarantine: Duration,
+    auth_quarantine: Duration,
+    cursor: AtomicUsize,
+}
+
+impl Default for KeyPool {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            selection: KeySelection::default(),
+            rate_limit_quarantine: Duration::from_secs(30),
+            auth_quarantine: Duration::from_secs(600),
+            cursor: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl KeyPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(self, key: &str) -> Self {
+        //! Adds a key of weight 1, labeled by its position in the pool (`key-0`, `key-1`, ...)
+        let label = format!("key-{}", self.keys.len());
+        self.with_labeled_key(&label, key, 1)
+    }
+
+    pub fn with_labeled_key(mut self, label: &str, key: &str, weight: u32) -> Self {
+        //! Adds a key, `label` identifies it in [`KeyPool::status`] and `weight` is only used by
+        //! [`KeySelection::Weighted`]
+        self.keys.push(PooledKey {
+            label: label.into(),
+            key: key.into(),
+            weight,
+            health: Mutex::new(Health::default()),
+        });
+        self
+    }
+
+    pub fn with_selection(mut self, selection: KeySelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_rate_limit_quarantine(mut self, quarantine: Duration) -> Self {
+        self.rate_limit_quarantine = quarantine;
+        self
+    }
+
+    pub fn with_auth_quarantine(mut self, quarantine: Duration) -> Self {
+        self.auth_quarantine = quarantine;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn status(&self) -> Vec<KeyStatus> {
+        //! Usage and health of every key, in the order they were added
+        let now = Instant::now();
+        self.keys
+            .iter()
+            .map(|key| {
+                let health = key.health.lock().unwrap();
+                KeyStatus {
+                    label: key.label.clone(),
+                    weight: key.weight,
+                    usage: health.usage.clone(),
+                    quarantined_for: health
+                        .quarantined_until
+                        .filter(|until| *until > now)
+                        .map(|until| until - now),
+                    last_rate_limited: health.last_rate_limited,
+                }
+            })
+            .collect()
+    }
+
+    /// Picks the key of the next request.
+    pub(crate) fn acquire(&self) -> anyhow::Result<KeyLease<'_>> {
+        anyhow::ensure!(!self.keys.is_empty(), "the key pool is empty");
+        let now = Instant::now();
+        let health: Vec<_> = self
+            .keys
+            .iter()
+            .map(|key| key.health.lock().unwrap())
+            .collect();
+        let cursor = self.cursor.fetch_add(1, Ordering::Relaxed);
+        // positions of the available keys, starting at the cursor
+        let available: Vec<usize> = (0..self.keys.len())
+            .map(|offset| (cursor + offset) % self.keys.len())
+            .filter(|&index| health[index].is_available(now))
+            .collect();
+
+        let index = match self.selection {
+            _ if available.is_empty() => (0..self.keys.len())
+                .min_by_key(|&index| health[index].quarantined_until)
+                .unwrap_or_default(),
+            KeySelection::RoundRobin => available[0],
+            KeySelection::LeastRecentlyRateLimited => available
+                .iter()
+                .copied()
+                .min_by_key(|&index| health[index].last_rate_limited)
+                .unwrap_or(available[0]),
+            KeySelection::Weighted => {
+                let total: u64 = available
+                    .iter()
+                    .map(|&index| self.keys[index].weight as u64)
+                    .sum();
+                let mut ticket = (cursor as u64) % total.max(1);
+                // in pool order, so every key owns a fixed range of tickets
+                let mut ordered = available.clone();
+                ordered.sort_unstable();
+                ordered
+                    .into_iter()
+                    .find(|&index| {
+                        let weight = self.keys[index].weight as u64;
+                        if ticket < weight {
+                            return true;
+                        }
+                        ticket -= weight;
+                        false
+                    })
+                    .unwrap_or(available[0])
+            }
+        };
+        Ok(KeyLease {
+            pool: self,
+            index,
+            cursor,
+        })
+    }
+}
+
+impl Debug for KeyPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyPool")
+            .field(
+                "keys",
+                &self.keys.iter().map(|key| &key.label).collect::<Vec<_>>(),
+            )
+            .field("selection", &self.selection)
+            .field("rate_limit_quarantine", &self.rate_limit_quarantine)
+            .field("auth_quarantine", &self.auth_quarantine)
+            .finish()
+    }
+}
+
+/// The key picked for a single request, reporting the outcome back to the pool.
+pub(crate) struct KeyLease<'a> {
+    pool: &'a KeyPool,
+    index: usize,
+    /// position of the rotation when the key was picked
+    cursor: usize,
+}
+
+impl KeyLease<'_> {
+    pub(crate) fn key(&self) -> &str {
+        self.pool.keys[self.index].key.expose()
+    }
+
+    pub(crate) fn release(self) {
+        //! Gives the key back unused (cache hit, short-circuited request, ...), the rotation is
+        //! rewound unless another request picked a key in the meantime
+        let _ = self.pool.cursor.compare_exchange(
+            self.cursor.wrapping_add(1),
+            self.cursor,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn finish(self, result: Result<&CompletionOption, &anyhow::Error>) {
+        let now = Instant::now();
+        let mut health = self.pool.keys[self.index].health.lock().unwrap();
+        health.usage.requests += 1;
+        match result {
+            Ok(completion) => {
+                if let Some(usage) = completion.usage() {
+                    health.usage.prompt_tokens += usage.prompt_tokens as u64;
+                    health.usage.completion_tokens += usage.completion_tokens as u64;
+                }
+            }
+            Err(err) => {
+                health.usage.failures += 1;
+                let status = err.downcast_ref::<ErrorResponse>().map(|res| res.code);
+                if status == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) {
+                    health.usage.rate_limited += 1;
+                    health.last_rate_limited = Some(now);
+                    health.quarantined_until = Some(now + self.pool.rate_limit_quarantine);
+                } else if status == Some(reqwest::StatusCode::UNAUTHORIZED) {
+                    health.usage.unauthorized += 1;
+                    health.quarantined_until = Some(now + self.pool.auth_quarantine);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod keys_test {
+    use std::sync::Arc;
+
+    use super::{KeyPool, KeySelection};
+    use crate::completion::{
+        cache::ResponseCache,
+        client::Groq,
+        request::builder::RequestBuilder,
+        transport::{fixtures, MockTransport},
+    };
+
+    const RATE_LIMITED: &str =
+        r#"{"error":{"message":"slow down","type":"tokens","code":"rate_limit_exceeded"}}"#;
+
+    fn sent_keys(transport: &MockTransport) -> Vec<String> {
+        transport
+            .requests()
+            .iter()
+            .filter_map(|req| {
+                req.headers
+                    .iter()
+                    .find(|(name, _)| name == "authorization")
+                    .map(|(_, value)| value.trim_start_matches("Bearer ").to_string())
+            })
+            .collect()
+    }
+
+    fn pick(pool: &KeyPool, n: usize) -> Vec<String> {
+        (0..n)
+            .map(|_| pool.acquire().unwrap().key().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn selection_strategies() {
+        let pool = KeyPool::new().with_key("a").with_key("b").with_key("c");
+        assert_eq!(pick(&pool, 4), vec!["a", "b", "c", "a"]);
+
+        let pool = KeyPool::new()
+            .with_labeled_key("heavy", "a", 3)
+            .with_labeled_key("light", "b", 1)
+            .with_selection(KeySelection::Weighted);
+        let picked = pick(&pool, 8);
+        assert_eq!(picked.iter().filter(|key| *key == "a").count(), 6);
+        let debug = format!("{:?}", pool);
+        assert!(debug.contains("heavy") && !debug.contains("\"a\""));
+    }
+
+    #[tokio::test]
+    async fn rotates_quarantines_and_tracks_usage() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport
+            .push_json(200, fixtures::completion_with_usage("hi", 3, 2))
+            .push_json(429, RATE_LIMITED)
+            .push_json(200, fixtures::completion_with_usage("hi", 3, 2))
+            .push_json(200, fixtures::completion_with_usage("hi", 3, 2));
+        let pool = KeyPool::new()
+            .with_key("key-a")
+            .with_key("key-b")
+            .with_selection(KeySelection::LeastRecentlyRateLimited);
+        let client = Groq::new("unused")
+            .with_transport(transport.clone())
+            .with_key_pool(pool);
+
+        let req = RequestBuilder::new("m".into());
+        client
+            .create(req.clone(), vec![fixtures::user("hello")])
+            .await?;
+        assert!(client
+            .create(req.clone(), vec![fixtures::user("hello")])
+            .await
+            .is_err());
+        client
+            .create(req.clone(), vec![fixtures::user("hello")])
+            .await?;
+        client.create(req, vec![fixtures::user("hello")]).await?;
+        // key-b is quarantined after its 429, key-a serves the rest
+        assert_eq!(
+            sent_keys(&transport),
+            vec!["key-a", "key-b", "key-a", "key-a"]
+        );
+
+        let status = client.key_pool().unwrap().status();
+        assert_eq!(status[0].usage.requests, 3);
+        assert_eq!(status[0].usage.prompt_tokens, 9);
+        assert_eq!(status[1].usage.rate_limited, 1);
+        assert!(status[1].quarantined_for.is_some());
+        Ok(())
+    }
+    #[tokio::test]
+    async fn cache_hits_leave_the_keys_alone() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_completion("hi").push_completion("hi");
+        let client = Groq::new("unused")
+            .with_transport(transport.clone())
+            .with_key_pool(KeyPool::new().with_key("key-a").with_key("key-b"))
+            .with_cache(ResponseCache::memory(8));
+
+        let req = || RequestBuilder::new("m".into()).with_temperature(0.0);
+        for _ in 0..3 {
+            client.create(req(), vec![fixtures::user("hello")]).await?;
+        }
+        client.create(req(), vec![fixtures::user("bye")]).await?;
+        // the cache hits neither count as requests nor take a turn of the rotation
+        assert_eq!(sent_keys(&transport), vec!["key-a", "key-b"]);
+        let status = client.key_pool().unwrap().status();
+        assert_eq!(status[0].usage.requests, 1);
+        assert_eq!(status[0].usage.prompt_tokens, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,537 @@
+//! Usage accounting and spend budgets, see [`UsageLedger`].
+use std::{collections::BTreeMap, fmt::Display, sync::Mutex};
+
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::response::UsageInfo;
+
+/// Price of a model in dollars per million tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+impl Pricing {
+    pub fn new(prompt_per_million: f64, completion_per_million: f64) -> Self {
+        Self {
+            prompt_per_million,
+            completion_per_million,
+        }
+    }
+
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.prompt_per_million
+            + completion_tokens as f64 * self.completion_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// A completion call recorded in the ledger.
+/// - cost, in dollars, 0 for models without a [`Pricing`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub at: DateTime<Utc>,
+    pub model: String,
+    pub user: Option<String>,
+    pub tags: Vec<String>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost: f64,
+}
+
+/// Sum of the entries of a group, see [`UsageLedger::totals`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, entry: &LedgerEntry) {
+        self.requests += 1;
+        self.prompt_tokens += entry.prompt_tokens;
+        self.completion_tokens += entry.completion_tokens;
+        self.total_tokens += entry.total_tokens;
+        self.cost += entry.cost;
+    }
+}
+
+/// How [`UsageLedger::totals`] groups the entries, an entry counts once for each of its tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupBy {
+    Model,
+    User,
+    Tag,
+}
+
+/// Requests a [`Budget`] applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetScope {
+    All,
+    Model(String),
+    User(String),
+    Tag(String),
+}
+
+/// Window a [`Budget`] is counted over, in UTC calendar days and months.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+    Lifetime,
+}
+
+impl BudgetPeriod {
+    fn start(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let (year, month, day) = match self {
+            BudgetPeriod::Daily => (now.year(), now.month(), now.day()),
+            BudgetPeriod::Monthly => (now.year(), now.month(), 1),
+            BudgetPeriod::Lifetime => return None,
+        };
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).single()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLimit {
+    Tokens(u64),
+    Dollars(f64),
+}
+
+impl BudgetLimit {
+    fn spent(&self, totals: &UsageTotals) -> f64 {
+        match self {
+            BudgetLimit::Tokens(_) => totals.total_tokens as f64,
+            BudgetLimit::Dollars(_) => totals.cost,
+        }
+    }
+
+    fn amount(&self) -> f64 {
+        match self {
+            BudgetLimit::Tokens(tokens) => *tokens as f64,
+            BudgetLimit::Dollars(dollars) => *dollars,
+        }
+    }
+}
+
+/// Cap on the tokens or dollars spent by the requests of a scope over a period.
+/// ```ignore no_run
+/// let ledger = UsageLedger::new()
+///     .with_price("llama3-70b-8192", Pricing::new(0.59, 0.79))
+///     .with_budget(Budget::monthly(BudgetLimit::Dollars(50.0)).for_user("acme"))
+///     .with_budget(Budget::daily(BudgetLimit::Tokens(2_000_000)).for_tag("batch"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    pub scope: BudgetScope,
+    pub period: BudgetPeriod,
+    pub limit: BudgetLimit,
+}
+
+impl Budget {
+    pub fn daily(limit: BudgetLimit) -> Self {
+        Self {
+            scope: BudgetScope::All,
+            period: BudgetPeriod::Daily,
+            limit,
+        }
+    }
+
+    pub fn monthly(limit: BudgetLimit) -> Self {
+        Self {
+            scope: BudgetScope::All,
+            period: BudgetPeriod::Monthly,
+            limit,
+        }
+    }
+
+    pub fn lifetime(limit: BudgetLimit) -> Self {
+        Self {
+            scope: BudgetScope::All,
+            period: BudgetPeriod::Lifetime,
+            limit,
+        }
+    }
+
+    pub fn for_model(mut self, model: &str) -> Self {
+        self.scope = BudgetScope::Model(model.into());
+        self
+    }
+
+    pub fn for_user(mut self, user: &str) -> Self {
+        self.scope = BudgetScope::User(user.into());
+        self
+    }
+
+    pub fn for_tag(mut self, tag: &str) -> Self {
+        self.scope = BudgetScope::Tag(tag.into());
+        self
+    }
+
+    fn applies(&self, model: &str, user: Option<&str>, tags: &[String]) -> bool {
+        match &self.scope {
+            BudgetScope::All => true,
+            BudgetScope::Model(scope) => scope == model,
+            BudgetScope::User(scope) => user == Some(scope.as_str()),
+            BudgetScope::Tag(scope) => tags.contains(scope),
+        }
+    }
+}
+
+impl Display for Budget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let period = match self.period {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Monthly => "monthly",
+            BudgetPeriod::Lifetime => "lifetime",
+        };
+        match self.limit {
+            BudgetLimit::Tokens(tokens) => write!(f, "{} budget of {} tokens", period, tokens)?,
+            BudgetLimit::Dollars(dollars) => write!(f, "{} budget of ${:.2}", period, dollars)?,
+        }
+        match &self.scope {
+            BudgetScope::All => Ok(()),
+            BudgetScope::Model(model) => write!(f, " for model '{}'", model),
+            BudgetScope::User(user) => write!(f, " for user '{}'", user),
+            BudgetScope::Tag(tag) => write!(f, " for tag '{}'", tag),
+        }
+    }
+}
+
+/// Returned by `Groq::create` when a request falls under an exhausted [`Budget`], it can be
+/// recovered with `err.downcast_ref::<BudgetExceeded>()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    pub budget: Budget,
+    pub spent: f64,
+}
+
+impl Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} exhausted, spent {}", self.budget, self.spent)
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Record of the usage of the completion calls of a client, installed with
+/// [`Groq::with_ledger`](super::client::Groq::with_ledger).
+///
+/// Every call that reached groq is recorded with its model, the `user` field of the request and
+/// the tags given with [`RequestBuilder::with_tag`](super::request::builder::RequestBuilder::with_tag).
+/// Before sending a request the client checks the budgets that apply to it and refuses it with
+/// [`BudgetExceeded`] once one is exhausted. The usage of a call is only known once it is done, so a
+/// budget can be overrun by the calls in flight when it runs out.
+///
+/// Budgets keep running totals of their current period, so checking them does not depend on the
+/// entries kept for export. Use [`UsageLedger::drain`] to export and drop the entries of a long
+/// running client.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    prices: BTreeMap<String, Pricing>,
+    budgets: Vec<Budget>,
+    state: Mutex<LedgerState>,
+}
+
+/// Entries not drained yet and the spend of every budget, in the order of `budgets`
+#[derive(Debug, Default)]
+struct LedgerState {
+    entries: Vec<LedgerEntry>,
+    windows: Vec<BudgetWindow>,
+}
+
+/// Usage of a budget since the start of its current period, None for lifetime budgets
+#[derive(Debug, Default)]
+struct BudgetWindow {
+    start: Option<DateTime<Utc>>,
+    totals: UsageTotals,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_price(mut self, model: &str, pricing: Pricing) -> Self {
+        self.prices.insert(model.into(), pricing);
+        self
+    }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budgets.push(budget);
+        self.state
+            .get_mut()
+            .unwrap()
+            .windows
+            .push(BudgetWindow::default());
+        self
+    }
+
+    pub fn budgets(&self) -> &[Budget] {
+        &self.budgets
+    }
+
+    pub fn record(&self, model: &str, user: Option<&str>, tags: &[String], usage: &UsageInfo) {
+        //! Non Consuming
+        //! Adds a call to the ledger, done by the client for every call that reached groq
+        self.record_at(Utc::now(), model, user, tags, usage);
+    }
+
+    fn record_at(
+        &self,
+        at: DateTime<Utc>,
+        model: &str,
+        user: Option<&str>,
+        tags: &[String],
+        usage: &UsageInfo,
+    ) {
+        let (prompt_tokens, completion_tokens) =
+            (usage.prompt_tokens as u64, usage.completion_tokens as u64);
+        let cost = self.prices.get(model).map_or(0.0, |pricing| {
+            pricing.cost(prompt_tokens, completion_tokens)
+        });
+        let entry = LedgerEntry {
+            at,
+            model: model.into(),
+            user: user.map(Into::into),
+            tags: tags.to_vec(),
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: usage.total_tokens as u64,
+            cost,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        for (budget, window) in self.budgets.iter().zip(&mut state.windows) {
+            if !budget.applies(model, user, tags) {
+                continue;
+            }
+            let start = budget.period.start(at);
+            if start > window.start {
+                *window = BudgetWindow {
+                    start,
+                    totals: UsageTotals::default(),
+                };
+            }
+            // entries of a period already over do not count against the current one
+            if start == window.start {
+                window.totals.add(&entry);
+            }
+        }
+        state.entries.push(entry);
+    }
+
+    pub fn check(
+        &self,
+        model: &str,
+        user: Option<&str>,
+        tags: &[String],
+    ) -> Result<(), BudgetExceeded> {
+        //! Fails with the first exhausted budget applying to a request of `model`, `user` and `tags`
+        self.check_at(Utc::now(), model, user, tags)
+    }
+
+    fn check_at(
+        &self,
+        now: DateTime<Utc>,
+        model: &str,
+        user: Option<&str>,
+        tags: &[String],
+    ) -> Result<(), BudgetExceeded> {
+        let state = self.state.lock().unwrap();
+        for (budget, window) in self.budgets.iter().zip(&state.windows) {
+            if !budget.applies(model, user, tags) {
+                continue;
+            }
+            // a window from an earlier period has nothing spent in the current one
+            let spent = if window.start == budget.period.start(now) {
+                budget.limit.spent(&window.totals)
+            } else {
+                0.0
+            };
+            if spent >= budget.limit.amount() {
+                return Err(BudgetExceeded {
+                    budget: budget.clone(),
+                    spent,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Vec<LedgerEntry> {
+        //! The entries not drained yet
+        self.state.lock().unwrap().entries.clone()
+    }
+
+    pub fn drain(&self) -> Vec<LedgerEntry> {
+        //! Returns the entries and drops them from the ledger, e.g. after exporting them.
+        //! Budgets are not affected.
+        std::mem::take(&mut self.state.lock().unwrap().entries)
+    }
+
+    pub fn clear(&self) {
+        //! Drops the entries, budgets are not affected
+        self.state.lock().unwrap().entries.clear();
+    }
+
+    pub fn totals(&self, group: GroupBy) -> BTreeMap<String, UsageTotals> {
+        //! Usage of the entries not drained yet summed per model, user or tag. Entries without a
+        //! user are grouped under an empty user and entries without tags are left out of the tag
+        //! groups.
+        let mut totals: BTreeMap<String, UsageTotals> = BTreeMap::new();
+        for entry in self.state.lock().unwrap().entries.iter() {
+            let keys = match group {
+                GroupBy::Model => vec![entry.model.clone()],
+                GroupBy::User => vec![entry.user.clone().unwrap_or_default()],
+                GroupBy::Tag => entry.tags.clone(),
+            };
+            for key in keys {
+                totals.entry(key).or_default().add(entry);
+            }
+        }
+        totals
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        //! Every entry as a JSON array
+        Ok(serde_json::to_string_pretty(
+            &self.state.lock().unwrap().entries,
+        )?)
+    }
+
+    pub fn to_csv(&self) -> String {
+        //! Every entry as CSV with a header line, tags are joined with `;`
+        let mut csv =
+            String::from("at,model,user,tags,prompt_tokens,completion_tokens,total_tokens,cost\n");
+        for entry in self.state.lock().unwrap().entries.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                entry.at.to_rfc3339(),
+                csv_field(&entry.model),
+                csv_field(entry.user.as_deref().unwrap_or_default()),
+                csv_field(&entry.tags.join(";")),
+                entry.prompt_tokens,
+                entry.completion_tokens,
+                entry.total_tokens,
+                entry.cost
+            ));
+        }
+        csv
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod ledger_test {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{Budget, BudgetExceeded, BudgetLimit, GroupBy, Pricing, UsageLedger};
+    use crate::completion::{
+        client::Groq,
+        request::builder::RequestBuilder,
+        response::UsageInfo,
+        transport::{fixtures, MockTransport},
+    };
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> UsageInfo {
+        UsageInfo {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn budgets_reset_with_their_period() {
+        let ledger = UsageLedger::new()
+            .with_price("big", Pricing::new(1.0, 2.0))
+            .with_budget(Budget::daily(BudgetLimit::Tokens(1_000)).for_user("acme"))
+            .with_budget(Budget::monthly(BudgetLimit::Dollars(1.0)).for_model("big"));
+        let day1 = Utc.with_ymd_and_hms(2024, 6, 1, 10, 0, 0).unwrap();
+        let day2 = Utc.with_ymd_and_hms(2024, 6, 2, 10, 0, 0).unwrap();
+        let tags = vec!["batch".to_string()];
+
+        ledger.record_at(day1, "small", Some("acme"), &tags, &usage(700, 300));
+        let err = ledger
+            .check_at(day1, "small", Some("acme"), &[])
+            .unwrap_err();
+        assert_eq!(err.spent, 1_000.0);
+        assert!(ledger.check_at(day1, "small", Some("other"), &[]).is_ok());
+        assert!(ledger.check_at(day2, "small", Some("acme"), &[]).is_ok());
+
+        // $0.6 + $0.8
+        ledger.record_at(day2, "big", None, &tags, &usage(600_000, 400_000));
+        assert!(ledger.check_at(day2, "big", None, &[]).is_err());
+        let next_month = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        assert!(ledger.check_at(next_month, "big", None, &[]).is_ok());
+
+        let by_tag = ledger.totals(GroupBy::Tag);
+        assert_eq!(by_tag["batch"].requests, 2);
+        assert!((ledger.totals(GroupBy::Model)["big"].cost - 1.4).abs() < 1e-9);
+        assert_eq!(ledger.totals(GroupBy::User)["acme"].total_tokens, 1_000);
+
+        // exported entries are dropped, the budgets keep what was spent
+        assert_eq!(ledger.drain().len(), 2);
+        assert!(ledger.entries().is_empty());
+        assert!(ledger.check_at(day2, "big", None, &[]).is_err());
+        ledger.record_at(day2, "small", Some("acme"), &[], &usage(1, 1));
+        ledger.clear();
+        assert!(ledger.to_csv().lines().nth(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn client_records_and_refuses() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        let response = fixtures::completion_with_usage("hi", 600, 400);
+        transport
+            .push_json(200, response.clone())
+            .push_json(200, response);
+        let client = Groq::new("key")
+            .with_transport(transport.clone())
+            .with_ledger(UsageLedger::new().with_budget(Budget::daily(BudgetLimit::Tokens(1_500))));
+        let messages = vec![fixtures::user("hello")];
+        let req = RequestBuilder::new("m".into())
+            .with_user("acme, inc")
+            .with_tag("chat");
+
+        client.create(req.clone(), messages.clone()).await?;
+        client.create(req.clone(), messages.clone()).await?;
+        let err = client.create(req, messages).await.unwrap_err();
+        assert!(err.downcast_ref::<BudgetExceeded>().is_some());
+        assert_eq!(transport.requests().len(), 2);
+
+        let ledger = client.ledger().unwrap();
+        let csv = ledger.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .contains(",m,\"acme, inc\",chat,600,400,1000,0"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&ledger.to_json()?)?[1]["model"],
+            "m"
+        );
+        Ok(())
+    }
+}
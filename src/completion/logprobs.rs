@@ -0,0 +1,177 @@
+//! Log probabilities of the generated tokens, returned when the request enables
+//! [`with_logprobs`](super::request::builder::RequestBuilder::with_logprobs).
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+/// Log probability information of a choice.
+/// - content, one entry per generated token, in order
+///
+/// For stream completions every chunk carries the entries of its own tokens, the
+/// [`StreamAggregator`](super::aggregate::StreamAggregator) concatenates them.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct LogProbs {
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogProb>>,
+}
+
+/// Log probability of a generated token.
+/// - token, the token text
+/// - logprob, natural log of the probability of the token
+/// - bytes, the UTF-8 bytes of the token, useful when a character spans several tokens
+/// - top_logprobs, the most likely alternatives at this position (see `with_top_logprobs`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogProb {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogProb>,
+}
+
+/// One of the most likely tokens at a position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopLogProb {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+}
+
+impl LogProbs {
+    pub fn tokens(&self) -> &[TokenLogProb] {
+        self.content.as_deref().unwrap_or_default()
+    }
+
+    pub fn extend(&mut self, other: &LogProbs) {
+        //! Non Consuming
+        //! Appends the tokens of `other`, e.g. of the next stream chunk
+        if let Some(content) = &other.content {
+            self.content
+                .get_or_insert_with(Vec::new)
+                .extend(content.iter().cloned());
+        }
+    }
+
+    pub fn log_likelihood(&self) -> f64 {
+        //! Log likelihood of the whole sequence, the sum of the token log probabilities
+        self.tokens().iter().map(|token| token.logprob).sum()
+    }
+
+    pub fn perplexity(&self) -> Option<f64> {
+        //! `exp(-mean logprob)`, None without any token.
+        //! 1 means the model was certain of every token, higher is less confident.
+        let count = self.tokens().len();
+        if count == 0 {
+            return None;
+        }
+        Some((-self.log_likelihood() / count as f64).exp())
+    }
+
+    pub fn confidences(&self) -> Vec<(&str, f64)> {
+        //! Every token with its probability, in order
+        self.tokens()
+            .iter()
+            .map(|token| (token.token.as_str(), token.probability()))
+            .collect()
+    }
+
+    pub fn least_confident(&self) -> Option<&TokenLogProb> {
+        //! The token the model was the least sure about
+        self.tokens()
+            .iter()
+            .min_by(|a, b| a.logprob.total_cmp(&b.logprob))
+    }
+}
+
+impl TokenLogProb {
+    pub fn probability(&self) -> f64 {
+        self.logprob.exp()
+    }
+
+    pub fn margin(&self) -> Option<f64> {
+        //! Probability gap between this token and the best other alternative, None when no
+        //! alternative was returned
+        self.top_logprobs
+            .iter()
+            .filter(|top| top.token != self.token)
+            .map(|top| top.logprob)
+            .max_by(f64::total_cmp)
+            .map(|best| self.probability() - best.exp())
+    }
+}
+
+impl TopLogProb {
+    pub fn probability(&self) -> f64 {
+        self.logprob.exp()
+    }
+}
+
+impl Hash for LogProbs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.content.hash(state);
+    }
+}
+
+impl Hash for TokenLogProb {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.token.hash(state);
+        self.logprob.to_bits().hash(state);
+        self.bytes.hash(state);
+        self.top_logprobs.hash(state);
+    }
+}
+
+impl Hash for TopLogProb {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.token.hash(state);
+        self.logprob.to_bits().hash(state);
+        self.bytes.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod logprobs_test {
+    use crate::completion::response::{Response, StreamResponse};
+
+    #[test]
+    fn deserializes_and_scores_choice_logprobs() -> anyhow::Result<()> {
+        let res: Response = serde_json::from_str(
+            r#"{"id":"chatcmpl-1","object":"chat.completion","created":1718000000,"model":"m","system_fingerprint":null,
+            "choices":[{"index":0,"message":{"role":"assistant","content":"Hi!"},"finish_reason":"stop",
+            "logprobs":{"content":[
+                {"token":"Hi","logprob":-0.1,"bytes":[72,105],"top_logprobs":[{"token":"Hi","logprob":-0.1,"bytes":[72,105]},{"token":"Hello","logprob":-2.5,"bytes":null}]},
+                {"token":"!","logprob":-0.5,"bytes":[33],"top_logprobs":[]}
+            ]}}],
+            "usage":{"prompt_tokens":1,"completion_tokens":2,"total_tokens":3,"prompt_time":0.1,"completion_time":0.1,"total_time":0.2}}"#,
+        )?;
+        let logprobs = res.choices[0].logprobs.as_ref().unwrap();
+        assert_eq!(logprobs.tokens().len(), 2);
+        assert!((logprobs.log_likelihood() + 0.6).abs() < 1e-9);
+        assert!((logprobs.perplexity().unwrap() - 0.3f64.exp()).abs() < 1e-9);
+        assert_eq!(logprobs.least_confident().unwrap().token, "!");
+
+        let first = &logprobs.tokens()[0];
+        assert_eq!(first.bytes.as_deref(), Some(&b"Hi"[..]));
+        let margin = first.margin().unwrap();
+        assert!((margin - ((-0.1f64).exp() - (-2.5f64).exp())).abs() < 1e-9);
+        assert!(logprobs.tokens()[1].margin().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn stream_logprobs_are_concatenated() -> anyhow::Result<()> {
+        let chunk = |token: &str, logprob: f64| -> anyhow::Result<StreamResponse> {
+            Ok(serde_json::from_str(&format!(
+                r#"{{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{{"index":0,"delta":{{"content":"{0}"}},"logprobs":{{"content":[{{"token":"{0}","logprob":{1},"bytes":null,"top_logprobs":[]}}]}},"finish_reason":null}}],"x_groq":{{"id":"req_1"}}}}"#,
+                token, logprob
+            ))?)
+        };
+        let res = Response::from_stream(&[chunk("a", -1.0)?, chunk("b", -3.0)?])?;
+        let logprobs = res.choices[0].logprobs.as_ref().unwrap();
+        assert_eq!(logprobs.confidences().len(), 2);
+        assert_eq!(logprobs.perplexity(), Some(2f64.exp()));
+        Ok(())
+    }
+}
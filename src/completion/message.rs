@@ -1,11 +1,12 @@
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// 1:1 Mapping for Message Object used in the `messages` field groq completion API.
 ///
 /// Refer to [the official documentations](https://console.groq.com/docs/api-reference#chat-create)
 /// for more details
 ///
-#[derive(Debug, Serialize, Clone, Hash)]
+/// Deserializing picks the variant from the `role` field (`system`, `user`, `assistant` or `tool`).
+#[derive(Debug, Serialize, Clone)]
 #[serde(untagged)]
 pub enum Message {
     SystemMessage {
@@ -52,16 +53,124 @@ pub enum Message {
     },
 }
 
-#[derive(Debug, Serialize, Clone, Hash)]
+impl Message {
+    pub fn content(&self) -> Option<&str> {
+        match self {
+            Message::SystemMessage { content, .. }
+            | Message::UserMessage { content, .. }
+            | Message::AssistantMessage { content, .. }
+            | Message::ToolMessage { content, .. } => content.as_deref(),
+        }
+    }
+
+    pub fn content_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Message::SystemMessage { content, .. }
+            | Message::UserMessage { content, .. }
+            | Message::AssistantMessage { content, .. }
+            | Message::ToolMessage { content, .. } => content.as_mut(),
+        }
+    }
+}
+
+/// Fields of every message variant, used to deserialize [`Message`] by role
+#[derive(Deserialize)]
+struct RawMessage {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    role: String,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default)]
+    tool_call_id: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let RawMessage {
+            content,
+            name,
+            role,
+            tool_calls,
+            tool_call_id,
+        } = RawMessage::deserialize(deserializer)?;
+        Ok(match role.as_str() {
+            "system" => Message::SystemMessage {
+                content,
+                name,
+                role: Some(role),
+                tool_call_id,
+            },
+            "user" => Message::UserMessage {
+                content,
+                name,
+                role: Some(role),
+                tool_call_id,
+            },
+            "assistant" => Message::AssistantMessage {
+                content,
+                name,
+                role: Some(role),
+                tool_calls,
+                tool_call_id,
+            },
+            "tool" => Message::ToolMessage {
+                content,
+                name,
+                role: Some(role),
+                tool_call_id,
+            },
+            other => {
+                return Err(serde::de::Error::unknown_variant(
+                    other,
+                    &["system", "user", "assistant", "tool"],
+                ))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
 pub struct ToolCall {
     pub id: Option<String>,
-    #[serde(rename(serialize = "type"))]
+    #[serde(rename = "type")]
     pub tool_type: Option<String>,
     pub function: AssistantFunc,
 }
 
-#[derive(Debug, Serialize, Clone, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, Hash, PartialEq, Eq)]
 pub struct AssistantFunc {
     pub arguments: Option<String>,
     pub name: Option<String>,
 }
+
+#[cfg(test)]
+mod message_test {
+    use super::Message;
+
+    #[test]
+    fn deserializes_by_role() -> anyhow::Result<()> {
+        let messages: Vec<Message> = serde_json::from_str(
+            r#"[
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": null, "tool_calls": [{"id": "call_1", "type": "function", "function": {"name": "f", "arguments": "{}"}}]},
+                {"role": "tool", "content": "42", "tool_call_id": "call_1"}
+            ]"#,
+        )?;
+        assert!(matches!(messages[0], Message::SystemMessage { .. }));
+        assert!(matches!(messages[1], Message::UserMessage { .. }));
+        assert!(matches!(
+            &messages[2],
+            Message::AssistantMessage { tool_calls: Some(calls), .. } if calls.len() == 1
+        ));
+        assert!(matches!(messages[3], Message::ToolMessage { .. }));
+
+        let json = serde_json::to_string(&messages)?;
+        assert_eq!(serde_json::from_str::<Vec<Message>>(&json)?, messages);
+        assert!(serde_json::from_str::<Message>(r#"{"role": "robot"}"#).is_err());
+        Ok(())
+    }
+}
@@ -1,4 +1,27 @@
+pub mod agent;
+pub mod aggregate;
+pub mod batch;
+pub mod cache;
+pub mod cassette;
 pub mod client;
+pub mod conversation;
+pub mod credentials;
+pub mod fallback;
+pub mod fingerprint;
+mod instrument;
+pub mod interceptor;
+pub mod keys;
+pub mod ledger;
+pub mod logprobs;
 pub mod message;
+pub mod moderation;
+pub mod rag;
 pub mod request;
 pub mod response;
+pub mod retry;
+pub mod stream;
+#[cfg(feature = "opentelemetry")]
+pub mod telemetry;
+pub mod template;
+pub mod timings;
+pub mod transport;
@@ -0,0 +1,603 @@
+//! Content moderation with the Llama Guard models hosted by groq, see [`Moderator`] and
+//! [`ModerationGuard`].
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client::{CompletionOption, Groq},
+    interceptor::Interceptor,
+    message::Message,
+    request::{builder::RequestBuilder, Request},
+};
+
+pub const DEFAULT_GUARD_MODEL: &str = "meta-llama/llama-guard-4-12b";
+
+/// Hazard categories of the MLCommons taxonomy used by Llama Guard, `S1` to `S14`.
+/// Codes the taxonomy does not define (e.g. from a custom policy) are kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardCategory {
+    ViolentCrimes,
+    NonViolentCrimes,
+    SexRelatedCrimes,
+    ChildSexualExploitation,
+    Defamation,
+    SpecializedAdvice,
+    Privacy,
+    IntellectualProperty,
+    IndiscriminateWeapons,
+    Hate,
+    SuicideAndSelfHarm,
+    SexualContent,
+    Elections,
+    CodeInterpreterAbuse,
+    Other(String),
+}
+
+const CATEGORIES: [(HazardCategory, &str, &str); 14] = [
+    (HazardCategory::ViolentCrimes, "S1", "violent crimes"),
+    (HazardCategory::NonViolentCrimes, "S2", "non-violent crimes"),
+    (HazardCategory::SexRelatedCrimes, "S3", "sex-related crimes"),
+    (
+        HazardCategory::ChildSexualExploitation,
+        "S4",
+        "child sexual exploitation",
+    ),
+    (HazardCategory::Defamation, "S5", "defamation"),
+    (
+        HazardCategory::SpecializedAdvice,
+        "S6",
+        "specialized advice",
+    ),
+    (HazardCategory::Privacy, "S7", "privacy"),
+    (
+        HazardCategory::IntellectualProperty,
+        "S8",
+        "intellectual property",
+    ),
+    (
+        HazardCategory::IndiscriminateWeapons,
+        "S9",
+        "indiscriminate weapons",
+    ),
+    (HazardCategory::Hate, "S10", "hate"),
+    (
+        HazardCategory::SuicideAndSelfHarm,
+        "S11",
+        "suicide & self-harm",
+    ),
+    (HazardCategory::SexualContent, "S12", "sexual content"),
+    (HazardCategory::Elections, "S13", "elections"),
+    (
+        HazardCategory::CodeInterpreterAbuse,
+        "S14",
+        "code interpreter abuse",
+    ),
+];
+
+impl HazardCategory {
+    pub fn from_code(code: &str) -> Self {
+        //! Parses a code such as `S1`, case insensitive
+        let code = code.trim();
+        CATEGORIES
+            .iter()
+            .find(|(_, known, _)| known.eq_ignore_ascii_case(code))
+            .map(|(category, _, _)| category.clone())
+            .unwrap_or_else(|| HazardCategory::Other(code.to_string()))
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            HazardCategory::Other(code) => code,
+            known => CATEGORIES
+                .iter()
+                .find(|(category, _, _)| category == known)
+                .map(|(_, code, _)| *code)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        match self {
+            HazardCategory::Other(_) => "unknown category",
+            known => CATEGORIES
+                .iter()
+                .find(|(category, _, _)| category == known)
+                .map(|(_, _, description)| *description)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Display for HazardCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.code(), self.description())
+    }
+}
+
+/// Verdict of Llama Guard on the last turn of a conversation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Safe,
+    Unsafe(Vec<HazardCategory>),
+}
+
+impl Verdict {
+    pub fn parse(output: &str) -> anyhow::Result<Self> {
+        //! Parses the raw output of Llama Guard, `safe`, or `unsafe` followed by a line of comma
+        //! separated category codes, e.g. `unsafe\nS1,S3`
+        let mut lines = output.trim().lines();
+        match lines
+            .next()
+            .map(|line| line.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("safe") => Ok(Verdict::Safe),
+            Some("unsafe") => Ok(Verdict::Unsafe(
+                lines
+                    .flat_map(|line| line.split(','))
+                    .filter(|code| !code.trim().is_empty())
+                    .map(HazardCategory::from_code)
+                    .collect(),
+            )),
+            _ => anyhow::bail!("unexpected Llama Guard output {:?}", output),
+        }
+    }
+
+    pub fn is_safe(&self) -> bool {
+        matches!(self, Verdict::Safe)
+    }
+
+    pub fn categories(&self) -> &[HazardCategory] {
+        match self {
+            Verdict::Safe => &[],
+            Verdict::Unsafe(categories) => categories,
+        }
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Safe => write!(f, "safe"),
+            Verdict::Unsafe(categories) => {
+                let categories: Vec<_> = categories.iter().map(ToString::to_string).collect();
+                write!(f, "unsafe: {}", categories.join(", "))
+            }
+        }
+    }
+}
+
+/// Classifies messages with a Llama Guard model.
+/// ```ignore no_run
+/// let moderator = Moderator::new(client.clone());
+/// if let Verdict::Unsafe(categories) = moderator.classify_message(&input).await? {
+///     println!("refused: {:?}", categories);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Moderator {
+    client: Groq,
+    request: RequestBuilder,
+}
+
+impl Moderator {
+    pub fn new(client: Groq) -> Self {
+        //! Uses [`DEFAULT_GUARD_MODEL`]
+        Self {
+            client,
+            request: RequestBuilder::new(DEFAULT_GUARD_MODEL.to_string()).with_temperature(0.0),
+        }
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.request = self.request.with_model(model);
+        self
+    }
+
+    pub fn model(&self) -> &str {
+        self.request.model()
+    }
+
+    pub async fn classify_message(&self, content: &str) -> anyhow::Result<Verdict> {
+        //! Classifies a single user message
+        self.classify_conversation(&[Message::UserMessage {
+            content: Some(content.to_string()),
+            name: None,
+            role: Some("user".to_string()),
+            tool_call_id: None,
+        }])
+        .await
+    }
+
+    pub async fn classify_conversation(&self, messages: &[Message]) -> anyhow::Result<Verdict> {
+        //! Classifies the last turn of a conversation, the user prompt when it ends with a user
+        //! message or the answer when it ends with an assistant message.
+        //! Llama Guard only reads user and assistant turns, system and tool messages and empty
+        //! turns are left out. A conversation without turns is safe.
+        let turns = guarded_turns(messages);
+        if turns.is_empty() {
+            return Ok(Verdict::Safe);
+        }
+        let response = self
+            .client
+            .create(self.request.clone(), turns)
+            .await?
+            .into_response()?;
+        let output = response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.as_str())
+            .unwrap_or_default();
+        Verdict::parse(output)
+    }
+}
+
+/// User and assistant turns of `messages` with text, stripped down to their role and content
+fn guarded_turns(messages: &[Message]) -> Vec<Message> {
+    messages
+        .iter()
+        .filter_map(|msg| {
+            let content = Some(msg.content().filter(|c| !c.trim().is_empty())?.to_string());
+            match msg {
+                Message::UserMessage { .. } => Some(Message::UserMessage {
+                    content,
+                    name: None,
+                    role: Some("user".to_string()),
+                    tool_call_id: None,
+                }),
+                Message::AssistantMessage { .. } => Some(Message::AssistantMessage {
+                    content,
+                    name: None,
+                    role: Some("assistant".to_string()),
+                    tool_calls: None,
+                    tool_call_id: None,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Side of a completion a guard looked at.
+/// - Input, the messages sent to the model
+/// - Output, the answer of the model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardStage {
+    Input,
+    Output,
+}
+
+impl Display for GuardStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuardStage::Input => write!(f, "input"),
+            GuardStage::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// What a [`ModerationGuard`] does with an unsafe turn.
+/// - Off, the turn is not classified
+/// - Flag, the turn goes through and is recorded, see [`ModerationGuard::flagged`]
+/// - Block, the completion fails with [`ContentBlocked`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardMode {
+    Off,
+    Flag,
+    #[default]
+    Block,
+}
+
+/// Unsafe turn let through by a guard in [`GuardMode::Flag`].
+/// - model, the model of the guarded request
+/// - content, the text of the turn
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlaggedTurn {
+    pub stage: GuardStage,
+    pub model: String,
+    pub content: String,
+    pub verdict: Verdict,
+}
+
+/// Returned by `Groq::create` when a [`ModerationGuard`] blocks a turn, it can be recovered with
+/// `err.downcast_ref::<ContentBlocked>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentBlocked {
+    pub stage: GuardStage,
+    pub verdict: Verdict,
+}
+
+impl Display for ContentBlocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} blocked by moderation, {}", self.stage, self.verdict)
+    }
+}
+
+impl std::error::Error for ContentBlocked {}
+
+/// Interceptor classifying the last turn of every request before it is sent and every choice of
+/// the answer once it is complete, installed with [`Groq::with_interceptor`](super::client::Groq::with_interceptor).
+///
+/// Clones share the flagged turns, so keep a clone to read them.
+/// Requests to the guard model itself are let through, so the moderator may use the guarded client.
+/// Stream answers are classified once the stream ended, after `on_chunk` saw every chunk.
+/// ```ignore no_run
+/// let guard = ModerationGuard::new(Moderator::new(client.clone())).with_output(GuardMode::Flag);
+/// let client = client.with_interceptor(guard.clone());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ModerationGuard {
+    moderator: Moderator,
+    input: GuardMode,
+    output: GuardMode,
+    flagged: Arc<Mutex<Vec<FlaggedTurn>>>,
+}
+
+impl ModerationGuard {
+    pub fn new(moderator: Moderator) -> Self {
+        //! Blocks unsafe input and output
+        Self {
+            moderator,
+            input: GuardMode::Block,
+            output: GuardMode::Block,
+            flagged: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn with_input(mut self, mode: GuardMode) -> Self {
+        self.input = mode;
+        self
+    }
+
+    pub fn with_output(mut self, mode: GuardMode) -> Self {
+        self.output = mode;
+        self
+    }
+
+    pub fn flagged(&self) -> Vec<FlaggedTurn> {
+        self.flagged.lock().unwrap().clone()
+    }
+
+    pub fn take_flagged(&self) -> Vec<FlaggedTurn> {
+        //! Returns the flagged turns and forgets them
+        std::mem::take(&mut *self.flagged.lock().unwrap())
+    }
+
+    async fn check(
+        &self,
+        stage: GuardStage,
+        req: &Request,
+        messages: &[Message],
+    ) -> anyhow::Result<()> {
+        let mode = match stage {
+            GuardStage::Input => self.input,
+            GuardStage::Output => self.output,
+        };
+        if mode == GuardMode::Off || req.model() == self.moderator.model() {
+            return Ok(());
+        }
+        let verdict = self.moderator.classify_conversation(messages).await?;
+        if verdict.is_safe() {
+            return Ok(());
+        }
+        if mode == GuardMode::Block {
+            anyhow::bail!(ContentBlocked { stage, verdict });
+        }
+        let content = guarded_turns(messages)
+            .last()
+            .and_then(|msg| msg.content().map(String::from))
+            .unwrap_or_default();
+        self.flagged.lock().unwrap().push(FlaggedTurn {
+            stage,
+            model: req.model().to_string(),
+            content,
+            verdict,
+        });
+        Ok(())
+    }
+}
+
+impl Interceptor for ModerationGuard {
+    fn on_request<'a>(
+        &'a self,
+        req: &'a mut Request,
+        _headers: &'a mut Vec<(String, String)>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<CompletionOption>>> {
+        Box::pin(async {
+            self.check(GuardStage::Input, req, req.messages()).await?;
+            Ok(None)
+        })
+    }
+
+    fn on_completion<'a>(
+        &'a self,
+        req: &'a Request,
+        completion: &'a mut CompletionOption,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async {
+            if self.output == GuardMode::Off || req.model() == self.moderator.model() {
+                return Ok(());
+            }
+            let response = completion.clone().into_response()?;
+            // every choice is classified, answers with tool calls only have no turn of their own
+            for choice in &response.choices {
+                if choice.message.content.trim().is_empty() {
+                    continue;
+                }
+                let mut messages = req.messages().to_vec();
+                messages.push(Message::AssistantMessage {
+                    content: Some(choice.message.content.clone()),
+                    name: None,
+                    role: Some("assistant".to_string()),
+                    tool_calls: None,
+                    tool_call_id: None,
+                });
+                self.check(GuardStage::Output, req, &messages).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod moderation_test {
+    use std::sync::Arc;
+
+    use super::{
+        ContentBlocked, GuardMode, GuardStage, HazardCategory, ModerationGuard, Moderator, Verdict,
+        DEFAULT_GUARD_MODEL,
+    };
+    use crate::completion::{
+        client::Groq,
+        message::Message,
+        request::builder::RequestBuilder,
+        transport::{fixtures, MockTransport},
+    };
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::SystemMessage {
+                content: Some("be nice".to_string()),
+                name: None,
+                role: Some("system".to_string()),
+                tool_call_id: None,
+            },
+            fixtures::user("hello"),
+        ]
+    }
+
+    #[test]
+    fn parses_verdicts() -> anyhow::Result<()> {
+        assert_eq!(Verdict::parse("safe")?, Verdict::Safe);
+        assert_eq!(
+            Verdict::parse("\nunsafe\nS1,S3")?,
+            Verdict::Unsafe(vec![
+                HazardCategory::ViolentCrimes,
+                HazardCategory::SexRelatedCrimes
+            ])
+        );
+        let verdict = Verdict::parse("unsafe\ns14, S99")?;
+        assert_eq!(
+            verdict.categories(),
+            [
+                HazardCategory::CodeInterpreterAbuse,
+                HazardCategory::Other("S99".into())
+            ]
+        );
+        assert_eq!(HazardCategory::SuicideAndSelfHarm.code(), "S11");
+        assert_eq!(HazardCategory::Hate.to_string(), "S10 (hate)");
+        assert!(Verdict::parse("I cannot help with that").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn guard_blocks_input_and_flags_output() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_completion("unsafe\nS1");
+        let client = Groq::new("key").with_transport(transport.clone());
+        let guard = ModerationGuard::new(Moderator::new(client.clone()));
+        let guarded = client.clone().with_interceptor(guard.clone());
+
+        let err = guarded
+            .create(RequestBuilder::new("m".into()), messages())
+            .await
+            .unwrap_err();
+        let blocked = err.downcast_ref::<ContentBlocked>().unwrap();
+        assert_eq!(blocked.stage, GuardStage::Input);
+        assert_eq!(
+            blocked.verdict.categories(),
+            [HazardCategory::ViolentCrimes]
+        );
+        let sent = transport.requests();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].body["model"], DEFAULT_GUARD_MODEL);
+        assert_eq!(sent[0].body["messages"].as_array().unwrap().len(), 1);
+
+        let guard = guard.with_output(GuardMode::Flag);
+        let guarded = client.with_interceptor(guard.clone());
+        transport
+            .push_completion("safe")
+            .push_completion("some lyrics")
+            .push_completion("unsafe\nS8");
+        let completion = guarded
+            .create(RequestBuilder::new("m".into()), messages())
+            .await?
+            .into_response()?;
+        assert_eq!(completion.choices[0].message.content, "some lyrics");
+
+        let flagged = guard.take_flagged();
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].stage, GuardStage::Output);
+        assert_eq!(flagged[0].content, "some lyrics");
+        assert_eq!(
+            flagged[0].verdict.categories(),
+            [HazardCategory::IntellectualProperty]
+        );
+        let output_check = &transport.requests()[3].body["messages"];
+        assert_eq!(output_check[1]["role"], "assistant");
+        assert!(guard.flagged().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn output_guard_checks_every_choice() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        let client = Groq::new("key").with_transport(transport.clone());
+        let guard = ModerationGuard::new(Moderator::new(client.clone())).with_input(GuardMode::Off);
+        let guarded = client.with_interceptor(guard);
+
+        let choice = |index: u32, content: &str| {
+            serde_json::json!({
+                "index": index,
+                "message": { "role": "assistant", "content": content },
+                "finish_reason": "stop",
+                "logprobs": null,
+            })
+        };
+        let mut answer: serde_json::Value = serde_json::from_str(&fixtures::completion("a poem"))?;
+        answer["choices"] = serde_json::json!([choice(0, "a poem"), choice(1, "a threat")]);
+        transport
+            .push_json(200, answer.to_string())
+            .push_completion("safe")
+            .push_completion("unsafe\nS1");
+        let err = guarded
+            .create(RequestBuilder::new("m".into()).with_n(2), messages())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ContentBlocked>().unwrap().stage,
+            GuardStage::Output
+        );
+        let second_check = &transport.requests()[2].body["messages"];
+        assert!(second_check[1]["content"]
+            .as_str()
+            .unwrap()
+            .contains("a threat"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn output_guard_skips_tool_call_answers() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        let client = Groq::new("key").with_transport(transport.clone());
+        let guard = ModerationGuard::new(Moderator::new(client.clone())).with_input(GuardMode::Off);
+        let guarded = client.with_interceptor(guard.clone());
+
+        transport.push_tool_call("call_1", "search", "{}");
+        guarded
+            .create(RequestBuilder::new("m".into()), messages())
+            .await?;
+        assert_eq!(transport.requests().len(), 1);
+        assert!(guard.flagged().is_empty());
+        Ok(())
+    }
+}
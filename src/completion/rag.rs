@@ -0,0 +1,526 @@
+//! Retrieval augmented generation: chunks of documents relevant to the question are put in the
+//! prompt and the chunks cited by the answer are linked back to their sources, see [`Rag`].
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use super::{client::Groq, message::Message, request::builder::RequestBuilder, response::Response};
+
+pub const DEFAULT_INSTRUCTIONS: &str = "Answer using only the context below. Cite the chunks you \
+use by their id in square brackets, e.g. [guide.md#2]. If the context does not contain the \
+answer, say so.";
+
+/// A piece of a document.
+/// - id, unique among the chunks of a retriever, cited by the model in square brackets so it
+///   should not contain `[`, `]` or `,`
+/// - source, where the chunk comes from, e.g. a path or an URL
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Chunk {
+    pub id: String,
+    pub source: String,
+    pub text: String,
+}
+
+impl Chunk {
+    pub fn new(id: &str, source: &str, text: &str) -> Self {
+        Self {
+            id: id.into(),
+            source: source.into(),
+            text: text.into(),
+        }
+    }
+
+    pub fn split(source: &str, text: &str, max_tokens: usize) -> Vec<Self> {
+        //! Splits a document on blank lines, joining paragraphs while they fit in `max_tokens`.
+        //! Chunks are numbered from 1, `source#1`, `source#2`, ...
+        //! A paragraph larger than `max_tokens` makes a chunk of its own.
+        let mut texts: Vec<String> = Vec::new();
+        for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+            match texts.last_mut() {
+                Some(last) if estimate_tokens(last) + estimate_tokens(paragraph) <= max_tokens => {
+                    last.push_str("\n\n");
+                    last.push_str(paragraph);
+                }
+                _ => texts.push(paragraph.to_string()),
+            }
+        }
+        texts
+            .into_iter()
+            .enumerate()
+            .map(|(n, text)| Self::new(&format!("{}#{}", source, n + 1), source, &text))
+            .collect()
+    }
+
+    fn render(&self) -> String {
+        format!("[{}] (source: {})\n{}", self.id, self.source, self.text)
+    }
+}
+
+/// A chunk found for a query, higher scores are more relevant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoredChunk {
+    pub chunk: Chunk,
+    pub score: f64,
+}
+
+/// Finds the chunks relevant to a query, e.g. in a vector store or a search engine.
+pub trait Retriever: Debug + Send + Sync {
+    /// At most `limit` chunks, the most relevant first
+    fn retrieve<'a>(
+        &'a self,
+        query: &'a str,
+        limit: usize,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<ScoredChunk>>>;
+}
+
+/// In memory retriever ranking chunks with [Okapi BM25](https://en.wikipedia.org/wiki/Okapi_BM25).
+///
+/// Text is split into lowercase alphanumeric terms, without stemming or stop words.
+/// - k1, term frequency saturation, 1.2 by default
+/// - b, document length normalization, 0.75 by default
+#[derive(Debug, Clone)]
+pub struct Bm25Index {
+    chunks: Vec<Chunk>,
+    terms: Vec<HashMap<String, u32>>,
+    lengths: Vec<usize>,
+    doc_freq: HashMap<String, usize>,
+    k1: f64,
+    b: f64,
+}
+
+impl Default for Bm25Index {
+    fn default() -> Self {
+        Self {
+            chunks: Vec::new(),
+            terms: Vec::new(),
+            lengths: Vec::new(),
+            doc_freq: HashMap::new(),
+            k1: 1.2,
+            b: 0.75,
+        }
+    }
+}
+
+impl Bm25Index {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_params(mut self, k1: f64, b: f64) -> Self {
+        self.k1 = k1;
+        self.b = b;
+        self
+    }
+
+    pub fn with_chunk(mut self, chunk: Chunk) -> Self {
+        self.add(chunk);
+        self
+    }
+
+    pub fn with_chunks(mut self, chunks: impl IntoIterator<Item = Chunk>) -> Self {
+        for chunk in chunks {
+            self.add(chunk);
+        }
+        self
+    }
+
+    pub fn add(&mut self, chunk: Chunk) {
+        //! Indexes `chunk`, replacing the chunk with the same id
+        let mut terms: HashMap<String, u32> = HashMap::new();
+        let tokens = tokenize(&chunk.text);
+        let length = tokens.len();
+        for token in tokens {
+            *terms.entry(token).or_default() += 1;
+        }
+        for term in terms.keys() {
+            *self.doc_freq.entry(term.clone()).or_default() += 1;
+        }
+
+        match self.chunks.iter().position(|known| known.id == chunk.id) {
+            Some(at) => {
+                for term in self.terms[at].keys() {
+                    if let Some(count) = self.doc_freq.get_mut(term) {
+                        *count -= 1;
+                    }
+                }
+                self.doc_freq.retain(|_, count| *count > 0);
+                self.chunks[at] = chunk;
+                self.terms[at] = terms;
+                self.lengths[at] = length;
+            }
+            None => {
+                self.chunks.push(chunk);
+                self.terms.push(terms);
+                self.lengths.push(length);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Vec<ScoredChunk> {
+        //! The `limit` best chunks matching at least one term of `query`
+        let mut query = tokenize(query);
+        query.sort();
+        query.dedup();
+        let count = self.chunks.len() as f64;
+        let average = self.lengths.iter().sum::<usize>() as f64 / count.max(1.0);
+
+        let mut scored: Vec<(usize, f64)> = (0..self.chunks.len())
+            .map(|at| {
+                let norm = 1.0 - self.b + self.b * self.lengths[at] as f64 / average.max(1.0);
+                let score = query
+                    .iter()
+                    .filter_map(|term| {
+                        let tf = *self.terms[at].get(term)? as f64;
+                        let df = self.doc_freq[term] as f64;
+                        let idf = (1.0 + (count - df + 0.5) / (df + 0.5)).ln();
+                        Some(idf * tf * (self.k1 + 1.0) / (tf + self.k1 * norm))
+                    })
+                    .sum::<f64>();
+                (at, score)
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        // stable, so ties keep the insertion order
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(at, score)| ScoredChunk {
+                chunk: self.chunks[at].clone(),
+                score,
+            })
+            .collect()
+    }
+}
+
+impl Retriever for Bm25Index {
+    fn retrieve<'a>(
+        &'a self,
+        query: &'a str,
+        limit: usize,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<ScoredChunk>>> {
+        Box::pin(async move { Ok(self.search(query, limit)) })
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Rough token count of `text`, about 4 characters per token for english text
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// A chunk of the context cited by the answer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Citation {
+    pub chunk_id: String,
+    pub source: String,
+}
+
+/// Answer returned by [`Rag::ask`] and [`Rag::create`].
+/// - response, the completion, stream completions are aggregated
+/// - context, the chunks put in the prompt, the most relevant first
+/// - citations, the chunks of the context cited by the answer, in order of first citation
+/// - unknown_citations, bracketed ids of the answer matching no chunk of the context
+#[derive(Debug, Clone)]
+pub struct RagAnswer {
+    pub response: Response,
+    pub context: Vec<ScoredChunk>,
+    pub citations: Vec<Citation>,
+    pub unknown_citations: Vec<String>,
+}
+
+impl RagAnswer {
+    pub fn answer(&self) -> &str {
+        self.response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.as_str())
+            .unwrap_or_default()
+    }
+
+    pub fn sources(&self) -> Vec<&str> {
+        //! The sources of the cited chunks, without duplicates
+        let mut sources: Vec<&str> = Vec::new();
+        for citation in &self.citations {
+            if !sources.contains(&citation.source.as_str()) {
+                sources.push(&citation.source);
+            }
+        }
+        sources
+    }
+}
+
+/// Grounds completions in the chunks found by a [`Retriever`].
+///
+/// The question, the content of the last user message, is sent to the retriever. The chunks found
+/// are added to the prompt, the most relevant first while they fit in the context budget, in a
+/// system message placed after the leading system messages of the request.
+/// ```ignore no_run
+/// let index = Bm25Index::new().with_chunks(Chunk::split("guide.md", &guide, 200));
+/// let rag = Rag::new(client, index).with_context_tokens(1500);
+/// let answer = rag.ask(RequestBuilder::new("llama-3.3-70b-versatile".into()), "How do I deploy?").await?;
+/// println!("{}\nsources: {:?}", answer.answer(), answer.sources());
+/// ```
+/// - top_k, chunks asked to the retriever, 8 by default
+/// - context_tokens, budget of the chunks in the prompt, 2000 tokens by default, estimated at 4
+///   characters per token
+/// - instructions, put before the chunks, [`DEFAULT_INSTRUCTIONS`] by default
+#[derive(Debug, Clone)]
+pub struct Rag {
+    client: Groq,
+    retriever: Arc<dyn Retriever>,
+    top_k: usize,
+    context_tokens: usize,
+    instructions: String,
+}
+
+impl Rag {
+    pub fn new(client: Groq, retriever: impl Retriever + 'static) -> Self {
+        Self {
+            client,
+            retriever: Arc::new(retriever),
+            top_k: 8,
+            context_tokens: 2000,
+            instructions: DEFAULT_INSTRUCTIONS.into(),
+        }
+    }
+
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    pub fn with_context_tokens(mut self, tokens: usize) -> Self {
+        self.context_tokens = tokens;
+        self
+    }
+
+    pub fn with_instructions(mut self, instructions: &str) -> Self {
+        self.instructions = instructions.into();
+        self
+    }
+
+    pub async fn context(&self, question: &str) -> anyhow::Result<Vec<ScoredChunk>> {
+        //! The chunks found for `question` that fit in the context budget, chunks too large for
+        //! what is left of the budget are skipped
+        let mut left = self.context_tokens;
+        let found = self.retriever.retrieve(question, self.top_k).await?;
+        Ok(found
+            .into_iter()
+            .filter(|scored| {
+                let tokens = estimate_tokens(&scored.chunk.render());
+                let fits = tokens <= left;
+                if fits {
+                    left -= tokens;
+                }
+                fits
+            })
+            .collect())
+    }
+
+    pub fn prompt(&self, context: &[ScoredChunk]) -> Message {
+        //! The system message carrying `context`
+        let mut content = self.instructions.clone();
+        content.push_str("\n\nContext:");
+        for scored in context {
+            content.push_str("\n\n");
+            content.push_str(&scored.chunk.render());
+        }
+        Message::SystemMessage {
+            content: Some(content),
+            name: None,
+            role: Some("system".to_string()),
+            tool_call_id: None,
+        }
+    }
+
+    pub async fn ask(&self, req: RequestBuilder, question: &str) -> anyhow::Result<RagAnswer> {
+        //! Shorthand for [`Rag::create`] with a single user message
+        self.create(
+            req,
+            vec![Message::UserMessage {
+                content: Some(question.into()),
+                name: None,
+                role: Some("user".to_string()),
+                tool_call_id: None,
+            }],
+        )
+        .await
+    }
+
+    pub async fn create(
+        &self,
+        req: RequestBuilder,
+        mut messages: Vec<Message>,
+    ) -> anyhow::Result<RagAnswer> {
+        //! Sends `messages` with the context found for the last user message
+        let question = messages
+            .iter()
+            .rev()
+            .find(|msg| matches!(msg, Message::UserMessage { .. }))
+            .and_then(|msg| msg.content())
+            .ok_or_else(|| anyhow::anyhow!("no user message to retrieve context for"))?
+            .to_string();
+        let context = self.context(&question).await?;
+        let at = messages
+            .iter()
+            .position(|msg| !matches!(msg, Message::SystemMessage { .. }))
+            .unwrap_or(messages.len());
+        messages.insert(at, self.prompt(&context));
+
+        let mut answer = RagAnswer {
+            response: self.client.create(req, messages).await?.into_response()?,
+            context,
+            citations: Vec::new(),
+            unknown_citations: Vec::new(),
+        };
+        (answer.citations, answer.unknown_citations) = cite(answer.answer(), &answer.context);
+        Ok(answer)
+    }
+}
+
+/// Bracketed ids of `answer`, split into the chunks of `context` and the unknown ids
+fn cite(answer: &str, context: &[ScoredChunk]) -> (Vec<Citation>, Vec<String>) {
+    let mut citations: Vec<Citation> = Vec::new();
+    let mut unknown: Vec<String> = Vec::new();
+    let ids = answer
+        .split('[')
+        .skip(1)
+        .filter_map(|rest| rest.split_once(']').map(|(inside, _)| inside))
+        .flat_map(|inside| inside.split(','))
+        .map(str::trim)
+        .filter(|id| !id.is_empty());
+    for id in ids {
+        match context.iter().find(|scored| scored.chunk.id == id) {
+            Some(scored) => {
+                if !citations.iter().any(|citation| citation.chunk_id == id) {
+                    citations.push(Citation {
+                        chunk_id: id.into(),
+                        source: scored.chunk.source.clone(),
+                    });
+                }
+            }
+            None => {
+                if !unknown.iter().any(|known| known == id) {
+                    unknown.push(id.into());
+                }
+            }
+        }
+    }
+    (citations, unknown)
+}
+
+#[cfg(test)]
+mod rag_test {
+    use std::sync::Arc;
+
+    use super::{Bm25Index, Chunk, Citation, Rag};
+    use crate::completion::{
+        client::Groq,
+        message::Message,
+        request::builder::RequestBuilder,
+        transport::{fixtures, MockTransport},
+    };
+
+    fn index() -> Bm25Index {
+        Bm25Index::new()
+            .with_chunk(Chunk::new(
+                "geo#1",
+                "geo.md",
+                "Paris is the capital of France.",
+            ))
+            .with_chunk(Chunk::new(
+                "geo#2",
+                "geo.md",
+                "Berlin is the capital of Germany.",
+            ))
+            .with_chunk(Chunk::new(
+                "food#1",
+                "food.md",
+                "France is famous for cheese, cheese and more cheese.",
+            ))
+    }
+
+    #[test]
+    fn ranks_and_splits_chunks() {
+        let mut index = index();
+        let ids: Vec<_> = index
+            .search("capital of France", 10)
+            .into_iter()
+            .map(|scored| scored.chunk.id)
+            .collect();
+        assert_eq!(ids, ["geo#1", "geo#2", "food#1"]);
+        assert_eq!(index.search("cheese", 10)[0].chunk.id, "food#1");
+        assert!(index.search("tokyo", 10).is_empty());
+
+        index.add(Chunk::new("food#1", "food.md", "Tokyo has great ramen."));
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.search("tokyo", 10)[0].chunk.id, "food#1");
+        assert!(index.search("cheese", 10).is_empty());
+
+        let chunks = Chunk::split("doc.md", "one two\n\nthree four\n\n\n\nfive", 5);
+        let texts: Vec<_> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(texts, ["one two\n\nthree four", "five"]);
+        assert_eq!(chunks[1].id, "doc.md#2");
+    }
+
+    #[tokio::test]
+    async fn injects_context_and_links_citations() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_completion("Paris [geo#1], not Berlin [geo#2, geo#1] [wiki].");
+        let rag = Rag::new(Groq::new("key").with_transport(transport.clone()), index())
+            .with_context_tokens(30);
+        assert_eq!(rag.context("capital of France").await?.len(), 2);
+
+        let messages = vec![
+            Message::SystemMessage {
+                content: Some("be brief".into()),
+                name: None,
+                role: Some("system".to_string()),
+                tool_call_id: None,
+            },
+            fixtures::user("capital of France?"),
+        ];
+        let answer = rag
+            .create(RequestBuilder::new("m".into()), messages)
+            .await?;
+        assert_eq!(
+            answer.citations,
+            [
+                Citation {
+                    chunk_id: "geo#1".into(),
+                    source: "geo.md".into()
+                },
+                Citation {
+                    chunk_id: "geo#2".into(),
+                    source: "geo.md".into()
+                },
+            ]
+        );
+        assert_eq!(answer.unknown_citations, ["wiki"]);
+        assert_eq!(answer.sources(), ["geo.md"]);
+
+        let sent = &transport.requests()[0].body["messages"];
+        assert_eq!(sent[0]["content"], "be brief");
+        let context = sent[1]["content"].as_str().unwrap();
+        assert!(context.contains("[geo#1] (source: geo.md)\nParis is the capital of France."));
+        assert!(!context.contains("food#1"));
+        assert_eq!(sent[2]["role"], "user");
+        Ok(())
+    }
+}
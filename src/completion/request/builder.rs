@@ -1,14 +1,21 @@
-use std::hash::Hash;
+use std::time::Duration;
 
 use super::{Message, Request, ResponseFormat, StopEnum, Tool, ToolChoiceEnum};
+use crate::completion::{
+    cache::CacheMode,
+    fallback::FallbackPolicy,
+    stream::{ReconnectPolicy, StreamOptions},
+};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio_util::sync::CancellationToken;
 
 /// Provides fluent api for building the request object for chat completion
 ///
 /// The field types, defaults and description could be found from [the official doc](https://console.groq.com/docs/api-reference#chat-create)
 ///
 /// Here and [Request](../../request/struct.Request.html) just a 1:1 mapping from it
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RequestBuilder {
     // unused for openai integration only
     logit_bias: Option<serde_json::Value>,
@@ -30,68 +37,102 @@ pub struct RequestBuilder {
     top_logprobs: Option<u8>,
     top_p: f32, // defaults to 1
     user: Option<String>,
+    // client side only, never sent to groq
+    stream_options: StreamOptions,
+    cache_mode: CacheMode,
+    fallback: Option<FallbackPolicy>,
+    tags: Vec<String>,
 }
 
-impl Hash for RequestBuilder {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.logprobs.hash(state);
-        ((self.frequency_penalty) as i32).hash(state);
-        self.max_tokens.hash(state);
-        self.messages.hash(state);
-        self.model.hash(state);
-        self.n.hash(state);
-        ((self.presence_penalty) as i32).hash(state);
-        self.response_format.hash(state);
-        self.seed.hash(state);
-        self.stop.hash(state);
-        self.stream.hash(state);
-        ((self.temperature) as i32).hash(state);
-        self.tool_choice.hash(state);
-        self.tools.hash(state);
-        self.top_logprobs.hash(state);
-        ((self.top_p) as i32).hash(state);
-        self.user.hash(state);
-    }
-}
-
-#[derive(Debug, PartialEq)]
+/// Partial set of request parameters, e.g. a named preset kept in a config file.
+///
+/// Every `None` field keeps the default of [`RequestBuilder::new`] when the config is turned into a
+/// builder with [`RequestBuilder::with_config`]. Configs can be layered with [`BuilderConfig::merge`]
+/// and (de)serialized with serde, see [`Presets`](super::preset::Presets) for loading them from
+/// JSON, TOML or YAML files.
+/// ```ignore no_run
+/// let cfg = BuilderConfig::new("llama3-8b-8192").merge(BuilderConfig {
+///     temperature: Some(0.0),
+///     ..Default::default()
+/// });
+/// let request = RequestBuilder::with_config(&cfg);
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BuilderConfig {
-    model: String,
-    logit_bias: Option<serde_json::Value>,
-    logprobs: Option<bool>,
-    frequency_penalty: Option<f32>,
-    max_tokens: Option<u32>,
-    n: Option<u32>,
-    presence_penalty: Option<f32>,
-    response_format: Option<ResponseFormat>,
-    seed: Option<i32>,
-    stop: Option<StopEnum>,
-    stream: Option<bool>,
-    temperature: Option<f32>,
-    tool_choice: Option<ToolChoiceEnum>,
-    tools: Option<Vec<Tool>>,
-    top_logprobs: Option<u8>,
-    top_p: Option<f32>,
-    user: Option<String>,
+    /// empty in a layer that leaves the model of the layer below unchanged
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StopEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoiceEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
 }
-impl Hash for BuilderConfig {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.model.hash(state);
-        self.logprobs.unwrap_or(false).hash(state);
-        (self.frequency_penalty.unwrap_or(0.0) as i32).hash(state);
-        self.max_tokens.hash(state);
-        self.n.hash(state);
-        (self.presence_penalty.unwrap_or(0.0) as i32).hash(state);
-        self.response_format.hash(state);
-        self.seed.hash(state);
-        self.stop.hash(state);
-        self.stream.hash(state);
-        (self.temperature.unwrap_or(1.0) as i32).hash(state);
-        self.tool_choice.hash(state);
-        self.tools.hash(state);
-        self.top_logprobs.hash(state);
-        (self.top_p.unwrap_or(1.0) as i32).hash(state);
-        self.user.hash(state);
+
+impl BuilderConfig {
+    pub fn new(model: &str) -> Self {
+        //! A config setting only the model
+        Self {
+            model: model.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn merge(self, overrides: BuilderConfig) -> Self {
+        //! Consuming
+        //! Layers `overrides` on top of `self`, every field set in `overrides` (a non empty model,
+        //! a `Some` value) replaces the one of `self`.
+        Self {
+            model: if overrides.model.is_empty() {
+                self.model
+            } else {
+                overrides.model
+            },
+            logit_bias: overrides.logit_bias.or(self.logit_bias),
+            logprobs: overrides.logprobs.or(self.logprobs),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            n: overrides.n.or(self.n),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            response_format: overrides.response_format.or(self.response_format),
+            seed: overrides.seed.or(self.seed),
+            stop: overrides.stop.or(self.stop),
+            stream: overrides.stream.or(self.stream),
+            temperature: overrides.temperature.or(self.temperature),
+            tool_choice: overrides.tool_choice.or(self.tool_choice),
+            tools: overrides.tools.or(self.tools),
+            top_logprobs: overrides.top_logprobs.or(self.top_logprobs),
+            top_p: overrides.top_p.or(self.top_p),
+            user: overrides.user.or(self.user),
+        }
     }
 }
 
@@ -140,13 +181,9 @@ impl RequestBuilder {
             builder_instance = builder_instance.with_temperature(temp);
         }
 
+        // set as is, a config loaded from a file may hold any string which `validate` reports
         if let Some(tool_choice) = cfg.tool_choice.clone() {
-            builder_instance = match tool_choice {
-                ToolChoiceEnum::Str(tool_str) => {
-                    builder_instance.with_tool_choice_string(tool_str).unwrap()
-                }
-                ToolChoiceEnum::Tool(tool_inst) => builder_instance.with_tool_choice(tool_inst),
-            }
+            builder_instance.tool_choice = Some(tool_choice);
         }
 
         if let Some(tools) = cfg.tools.clone() {
@@ -190,6 +227,23 @@ impl RequestBuilder {
         //! 1 to 1 copy of another RequestBuilder
         let mut builder = Self::with_config(&source.get_config());
         builder.messages.extend(source.messages.clone());
+        builder.stream_options = source.stream_options.clone();
+        builder.cache_mode = source.cache_mode;
+        builder.fallback = source.fallback.clone();
+        builder.tags = source.tags.clone();
+        builder
+    }
+
+    pub fn with_overrides(self, overrides: BuilderConfig) -> Self {
+        //! Consuming
+        //! Layers the fields set in `overrides` on top of this builder, see [`BuilderConfig::merge`].
+        //! The client side options (stream options, cache mode, fallback, tags) are kept.
+        let mut builder = Self::with_config(&self.get_config().merge(overrides));
+        builder.messages = self.messages;
+        builder.stream_options = self.stream_options;
+        builder.cache_mode = self.cache_mode;
+        builder.fallback = self.fallback;
+        builder.tags = self.tags;
         builder
     }
 
@@ -246,10 +300,28 @@ impl RequestBuilder {
             top_logprobs: None,
             top_p: 1.0,
             user: None,
+            stream_options: StreamOptions::default(),
+            cache_mode: CacheMode::default(),
+            fallback: None,
+            tags: Vec::new(),
         }
     }
 
     pub fn build(self) -> Request {
+        //! Builds the request, see [`RequestBuilder::try_build`] for a validated one.
+        //! Debug builds panic on parameters [`Request::validate`] rejects (out of range values,
+        //! conflicting fields, ...), the model and messages may still be missing.
+        let req = self.into_request();
+        if cfg!(debug_assertions) {
+            if let Err(err) = req.validate_parameters() {
+                panic!("{}", err);
+            }
+        }
+        req
+    }
+
+    /// The request as is, without any validation
+    pub(crate) fn into_request(self) -> Request {
         Request {
             logit_bias: self.logit_bias,
             logprobs: self.logprobs,
@@ -293,7 +365,7 @@ impl RequestBuilder {
     }
 
     pub(crate) fn with_messages(mut self, msgs: Vec<Message>) -> anyhow::Result<Self> {
-        anyhow::ensure!(msgs.len() > 0, "message cannot be empty");
+        anyhow::ensure!(!msgs.is_empty(), "message cannot be empty");
         self.messages = msgs;
         Ok(self)
     }
@@ -338,6 +410,70 @@ impl RequestBuilder {
         self
     }
 
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        //! Bounds the total duration of a stream completion, see [`StreamOptions`]
+        self.stream_options.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        //! Bounds the silence between two events of a stream completion, see [`StreamOptions`]
+        self.stream_options.idle_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        //! Stops a stream completion once `token` is cancelled, see [`StreamOptions`]
+        self.stream_options.cancellation = Some(token);
+        self
+    }
+
+    pub fn with_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        //! Resumes a stream completion whose connection dropped, see [`ReconnectPolicy`]
+        self.stream_options.reconnect = Some(policy);
+        self
+    }
+
+    pub fn stream_options(&self) -> &StreamOptions {
+        &self.stream_options
+    }
+
+    pub fn with_cache_mode(mut self, mode: CacheMode) -> Self {
+        //! How the request uses the cache of the client, see [`CacheMode`]
+        self.cache_mode = mode;
+        self
+    }
+
+    pub fn cache_mode(&self) -> CacheMode {
+        self.cache_mode
+    }
+
+    pub fn with_fallback(mut self, policy: FallbackPolicy) -> Self {
+        //! Models tried in order when this one fails, see [`FallbackPolicy`]
+        self.fallback = Some(policy);
+        self
+    }
+
+    pub fn without_fallback(mut self) -> Self {
+        self.fallback = None;
+        self
+    }
+
+    pub fn fallback(&self) -> Option<&FallbackPolicy> {
+        self.fallback.as_ref()
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        //! Labels the request in the [`UsageLedger`](crate::completion::ledger::UsageLedger) of
+        //! the client, tags are never sent to groq
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
     pub fn with_temperature(mut self, temp: f32) -> Self {
         self.temperature = temp;
         self
@@ -372,6 +508,26 @@ impl RequestBuilder {
         self
     }
 
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    pub fn top_p(&self) -> f32 {
+        self.top_p
+    }
+
+    pub fn max_tokens(&self) -> Option<u32> {
+        self.max_tokens
+    }
+
     pub fn is_stream(&self) -> bool {
         //! Check the request object is set to use stream for the completion response or not
         //! - true if the stream flag is on
@@ -432,6 +588,29 @@ mod builder_test {
         Ok(())
     }
 
+    #[test]
+    fn layers_merge_field_by_field() -> anyhow::Result<()> {
+        let base: BuilderConfig = serde_json::from_str(
+            r#"{"model":"llama3-8b-8192","temperature":0.2,"max_tokens":256}"#,
+        )?;
+        let layer: BuilderConfig = serde_json::from_str(r#"{"temperature":0.0,"seed":7}"#)?;
+        let cfg = base.merge(layer);
+
+        assert_eq!(cfg.model, "llama3-8b-8192");
+        assert_eq!(cfg.temperature, Some(0.0));
+        assert_eq!(cfg.max_tokens, Some(256));
+        assert_eq!(cfg.seed, Some(7));
+        assert_eq!(
+            serde_json::to_string(&cfg)?,
+            r#"{"model":"llama3-8b-8192","max_tokens":256,"seed":7,"temperature":0.0}"#
+        );
+
+        let builder = RequestBuilder::with_config(&cfg);
+        assert_eq!(builder.temperature(), 0.0);
+        assert_eq!(builder.max_tokens(), Some(256));
+        Ok(())
+    }
+
     #[test]
     fn copied_builder_should_have_eq_hash() -> anyhow::Result<()> {
         let mut hasher = DefaultHasher::new();
@@ -1,8 +1,10 @@
 use std::hash::Hash;
 
-use super::message::Message;
-use serde::Serialize;
+use super::{fingerprint::canonical, message::Message};
+use serde::{Deserialize, Serialize};
 pub mod builder;
+pub mod preset;
+pub mod validation;
 
 #[derive(Debug, Serialize)]
 pub struct Request {
@@ -48,56 +50,55 @@ pub struct Request {
     user: Option<String>,
 }
 
-impl Hash for Request {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.logprobs.hash(state);
-        ((self.frequency_penalty) as i32).hash(state);
-        self.max_tokens.hash(state);
-        self.messages.hash(state);
-        self.model.hash(state);
-        self.n.hash(state);
-        ((self.presence_penalty) as i32).hash(state);
-        self.response_format.hash(state);
-        self.seed.hash(state);
-        self.stop.hash(state);
-        self.stream.hash(state);
-        ((self.temperature) as i32).hash(state);
-        self.tool_choice.hash(state);
-        self.tools.hash(state);
-        self.top_logprobs.hash(state);
-        ((self.top_p) as i32).hash(state);
-        self.user.hash(state);
-    }
-}
-
 impl Request {
     pub fn is_stream(&self) -> bool {
         self.stream
     }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.into();
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn messages_mut(&mut self) -> &mut Vec<Message> {
+        //! Mutable access to the messages, e.g. for interceptors redacting their content
+        &mut self.messages
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
 }
 
-#[derive(Debug, Serialize, Hash, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Hash, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum ToolChoiceEnum {
     Str(String),
     Tool(Tool),
 }
 
-#[derive(Debug, Serialize, Hash, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Hash, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum StopEnum {
     Token(String),
     Tokens(Vec<String>),
 }
 
-#[derive(Debug, Serialize, Hash, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Hash, Clone, PartialEq)]
 pub struct Tool {
-    #[serde(rename(serialize = "type"))]
+    #[serde(rename = "type")]
     pub tool_type: String,
     pub function: Function,
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Function {
     pub description: Option<String>,
     pub name: Option<String>,
@@ -108,19 +109,30 @@ impl Hash for Function {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.description.hash(state);
         self.name.hash(state);
+        self.parameters.as_ref().map(canonical).hash(state);
     }
 }
 
-#[derive(Debug, Serialize, Hash, Clone, PartialEq)]
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        //! `parameters` are compared by their canonical form, like they are hashed
+        self.description == other.description
+            && self.name == other.name
+            && self.parameters.as_ref().map(canonical) == other.parameters.as_ref().map(canonical)
+    }
+}
+
+impl Eq for Function {}
+
+#[derive(Debug, Serialize, Deserialize, Hash, Clone, PartialEq)]
 pub struct ResponseFormat {
-    #[serde(rename(serialize = "type"))]
+    #[serde(rename = "type")]
     pub response_type: String,
 }
 
 #[cfg(test)]
 mod request_test {
     use crate::completion::request::*;
-    use anyhow::Context;
 
     #[test]
     fn init_request() -> anyhow::Result<()> {
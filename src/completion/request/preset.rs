@@ -0,0 +1,181 @@
+//! Named request presets loaded from config files.
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::builder::{BuilderConfig, RequestBuilder};
+
+/// Name of the preset every other preset is layered on
+pub const DEFAULT_PRESET: &str = "default";
+
+/// Named [`BuilderConfig`]s, e.g. kept in a config file next to the application.
+/// ```toml
+/// [default]
+/// model = "llama3-8b-8192"
+///
+/// [summarizer]
+/// temperature = 0.2
+/// max_tokens = 512
+///
+/// [classifier]
+/// temperature = 0.0
+/// seed = 7
+/// ```
+/// The `default` preset, when present, is the base layer of every other preset. Files are layered
+/// with [`Presets::merge`], e.g. a team wide file overridden by a local one.
+///
+/// JSON is always supported, TOML and YAML need the `toml` and `yaml` features.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Presets {
+    presets: BTreeMap<String, BuilderConfig>,
+}
+
+impl Presets {
+    pub fn from_json(content: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(content: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(content: &str) -> anyhow::Result<Self> {
+        Ok(serde_norway::from_str(content)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        //! Reads a preset file, the format is picked from the extension
+        //! (`json`, `toml`, `yaml` or `yml`)
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&content),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&content),
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Self::from_yaml(&content),
+            _ => anyhow::bail!(
+                "unsupported preset file '{}', supported extensions: {}",
+                path.display(),
+                Self::extensions().join(", ")
+            ),
+        }
+    }
+
+    fn extensions() -> Vec<&'static str> {
+        let mut extensions = vec!["json"];
+        if cfg!(feature = "toml") {
+            extensions.push("toml");
+        }
+        if cfg!(feature = "yaml") {
+            extensions.extend(["yaml", "yml"]);
+        }
+        extensions
+    }
+
+    pub fn insert(&mut self, name: &str, cfg: BuilderConfig) {
+        //! Non Consuming
+        self.presets.insert(name.into(), cfg);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(String::as_str)
+    }
+
+    pub fn merge(mut self, overrides: Presets) -> Self {
+        //! Consuming
+        //! Layers the presets of `overrides` on top of the ones of `self`, presets of the same
+        //! name are merged field by field with [`BuilderConfig::merge`].
+        for (name, cfg) in overrides.presets {
+            let merged = match self.presets.remove(&name) {
+                Some(base) => base.merge(cfg),
+                None => cfg,
+            };
+            self.presets.insert(name, merged);
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<BuilderConfig> {
+        //! The preset `name` layered on the `default` preset
+        let cfg = self.presets.get(name)?.clone();
+        match self.presets.get(DEFAULT_PRESET) {
+            Some(default) if name != DEFAULT_PRESET => Some(default.clone().merge(cfg)),
+            _ => Some(cfg),
+        }
+    }
+
+    pub fn builder(&self, name: &str) -> anyhow::Result<RequestBuilder> {
+        //! A request builder configured with the preset `name`
+        let cfg = self
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("unknown preset '{}'", name))?;
+        anyhow::ensure!(!cfg.model.is_empty(), "preset '{}' sets no model", name);
+        Ok(RequestBuilder::with_config(&cfg))
+    }
+}
+
+#[cfg(test)]
+mod preset_test {
+    use super::Presets;
+
+    const TEAM: &str = r#"{
+        "default": {"model": "llama3-8b-8192", "max_tokens": 256},
+        "summarizer": {"temperature": 0.2},
+        "classifier": {"temperature": 0.0, "seed": 7}
+    }"#;
+
+    #[test]
+    fn presets_layer_on_default_and_files() -> anyhow::Result<()> {
+        let local = Presets::from_json(r#"{"summarizer": {"max_tokens": 1024}}"#)?;
+        let presets = Presets::from_json(TEAM)?.merge(local);
+
+        let summarizer = presets.get("summarizer").unwrap();
+        assert_eq!(summarizer.model, "llama3-8b-8192");
+        assert_eq!(summarizer.temperature, Some(0.2));
+        assert_eq!(summarizer.max_tokens, Some(1024));
+
+        let classifier = presets.builder("classifier")?;
+        assert_eq!(classifier.model(), "llama3-8b-8192");
+        assert_eq!(classifier.max_tokens(), Some(256));
+        assert!(presets.builder("missing").is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn loads_toml() -> anyhow::Result<()> {
+        let presets = Presets::from_toml(
+            r#"
+            [default]
+            model = "llama3-8b-8192"
+
+            [summarizer]
+            temperature = 0.5
+            stop = ["\n\n"]
+            "#,
+        )?;
+        let summarizer = presets.get("summarizer").unwrap();
+        assert_eq!(summarizer.temperature, Some(0.5));
+        assert!(summarizer.stop.is_some());
+        Ok(())
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn loads_yaml() -> anyhow::Result<()> {
+        let presets = Presets::from_yaml(
+            "default:\n  model: llama3-8b-8192\nclassifier:\n  temperature: 0.0\n  response_format:\n    type: json_object\n",
+        )?;
+        let classifier = presets.get("classifier").unwrap();
+        assert_eq!(classifier.model, "llama3-8b-8192");
+        assert_eq!(
+            classifier.response_format.map(|fmt| fmt.response_type),
+            Some("json_object".to_string())
+        );
+        Ok(())
+    }
+}
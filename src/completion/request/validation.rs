@@ -0,0 +1,306 @@
+//! Client side validation of the request object, run by
+//! [`Groq::create`](crate::completion::client::Groq::create) before anything is sent and by
+//! [`RequestBuilder::build`] in debug builds.
+use std::{collections::HashSet, fmt::Display};
+
+use super::{builder::RequestBuilder, Request, StopEnum, ToolChoiceEnum};
+
+/// A single broken constraint of a request.
+/// - field, the request field at fault (the first one for cross field constraints)
+/// - message, what is wrong with it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Violation {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Every [`Violation`] found in a request.
+/// It can be recovered from the `anyhow::Error` returned by `Groq::create` with
+/// `err.downcast_ref::<ValidationError>()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid request:")?;
+        for violation in &self.violations {
+            write!(f, " {}: {};", violation.field, violation.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Limits of a model enforced on top of the generic checks.
+/// - context_window, the maximum number of tokens of prompt and completion together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModelLimits {
+    pub context_window: u32,
+}
+
+impl ModelLimits {
+    pub fn for_model(model: &str) -> Option<Self> {
+        //! Limits of the models hosted by groq, None for unknown models
+        let context_window = match model {
+            "mixtral-8x7b-32768" => 32_768,
+            "llama3-8b-8192" | "llama3-70b-8192" | "gemma-7b-it" | "gemma2-9b-it" => 8_192,
+            "llama-3.1-8b-instant" | "llama-3.1-70b-versatile" | "llama-3.3-70b-versatile" => {
+                131_072
+            }
+            _ => return None,
+        };
+        Some(Self { context_window })
+    }
+}
+
+#[derive(Default)]
+struct Violations(Vec<Violation>);
+
+impl Violations {
+    fn check(&mut self, ok: bool, field: &'static str, message: impl FnOnce() -> String) {
+        if !ok {
+            self.0.push(Violation {
+                field,
+                message: message(),
+            });
+        }
+    }
+
+    fn into_result(self) -> Result<(), ValidationError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations: self.0 })
+        }
+    }
+
+    fn range(&mut self, field: &'static str, value: f32, min: f32, max: f32) {
+        self.check((min..=max).contains(&value), field, || {
+            format!("must be between {} and {}, got {}", min, max, value)
+        });
+    }
+}
+
+impl Request {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        //! Checks the ranges of the parameters and the constraints between them, returning every
+        //! violation at once. The limits of the model are enforced when [`ModelLimits::for_model`]
+        //! knows it.
+        self.validate_with(ModelLimits::for_model(&self.model))
+    }
+
+    pub fn validate_with(&self, limits: Option<ModelLimits>) -> Result<(), ValidationError> {
+        //! Same as [`Request::validate`] with explicit model limits
+        let mut v = Violations::default();
+
+        v.check(!self.model.trim().is_empty(), "model", || {
+            "cannot be empty".into()
+        });
+        v.check(!self.messages.is_empty(), "messages", || {
+            "cannot be empty".into()
+        });
+        self.check_parameters(limits, &mut v);
+        v.into_result()
+    }
+
+    /// Checks of [`Request::validate`] that do not depend on the model and messages, which a
+    /// builder may only receive when the request is sent.
+    pub(crate) fn validate_parameters(&self) -> Result<(), ValidationError> {
+        let mut v = Violations::default();
+        self.check_parameters(ModelLimits::for_model(&self.model), &mut v);
+        v.into_result()
+    }
+
+    fn check_parameters(&self, limits: Option<ModelLimits>, v: &mut Violations) {
+        v.range("temperature", self.temperature, 0.0, 2.0);
+        v.range("top_p", self.top_p, 0.0, 1.0);
+        v.range("frequency_penalty", self.frequency_penalty, -2.0, 2.0);
+        v.range("presence_penalty", self.presence_penalty, -2.0, 2.0);
+        v.check(self.n >= 1, "n", || "must be at least 1".into());
+        v.check(!self.stream || self.n <= 1, "n", || {
+            "must be 1 when streaming".into()
+        });
+
+        if let Some(max_tokens) = self.max_tokens {
+            v.check(max_tokens >= 1, "max_tokens", || {
+                "must be at least 1".into()
+            });
+            if let Some(limits) = limits {
+                v.check(max_tokens <= limits.context_window, "max_tokens", || {
+                    format!(
+                        "exceeds the context window of {} ({} tokens)",
+                        self.model, limits.context_window
+                    )
+                });
+            }
+        }
+
+        if let Some(top_logprobs) = self.top_logprobs {
+            v.check(top_logprobs <= 20, "top_logprobs", || {
+                format!("must be between 0 and 20, got {}", top_logprobs)
+            });
+            v.check(self.logprobs, "top_logprobs", || {
+                "requires logprobs to be enabled".into()
+            });
+        }
+
+        if let Some(StopEnum::Tokens(stops)) = &self.stop {
+            v.check(stops.len() <= 4, "stop", || {
+                format!("allows at most 4 sequences, got {}", stops.len())
+            });
+        }
+
+        let format = self.response_format.response_type.as_str();
+        v.check(
+            format == "text" || format == "json_object",
+            "response_format",
+            || format!("type must be 'text' or 'json_object', got '{}'", format),
+        );
+
+        let mut names = HashSet::new();
+        for tool in self.tools.iter().flatten() {
+            match &tool.function.name {
+                Some(name) if !name.is_empty() => {
+                    v.check(names.insert(name.as_str()), "tools", || {
+                        format!("duplicate tool name '{}'", name)
+                    })
+                }
+                _ => v.check(false, "tools", || "every tool needs a function name".into()),
+            }
+        }
+
+        match &self.tool_choice {
+            Some(ToolChoiceEnum::Str(choice)) => {
+                v.check(choice == "auto" || choice == "none", "tool_choice", || {
+                    format!("must be 'none' or 'auto', got '{}'", choice)
+                });
+                v.check(choice == "none" || !names.is_empty(), "tool_choice", || {
+                    format!("is '{}' but no tools are provided", choice)
+                });
+            }
+            Some(ToolChoiceEnum::Tool(tool)) => {
+                let name = tool.function.name.as_deref().unwrap_or_default();
+                v.check(names.contains(name), "tool_choice", || {
+                    format!("names the tool '{}' which is not in tools", name)
+                });
+            }
+            None => {}
+        }
+    }
+}
+
+impl RequestBuilder {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        //! Validates the request this builder would build, see [`Request::validate`]
+        self.clone().into_request().validate()
+    }
+
+    pub fn try_build(self) -> Result<Request, ValidationError> {
+        //! Same as [`RequestBuilder::build`], failing when the request is invalid
+        let req = self.into_request();
+        req.validate()?;
+        Ok(req)
+    }
+}
+
+#[cfg(test)]
+mod validation_test {
+    use super::{ModelLimits, ValidationError};
+    use crate::completion::{
+        client::Groq,
+        message::Message,
+        request::{builder::RequestBuilder, Function, Tool},
+        transport::MockTransport,
+    };
+
+    fn messages() -> Vec<Message> {
+        vec![Message::UserMessage {
+            role: Some("user".to_string()),
+            content: Some("hello".to_string()),
+            name: None,
+            tool_call_id: None,
+        }]
+    }
+
+    fn tool(name: &str) -> Tool {
+        Tool {
+            tool_type: "function".into(),
+            function: Function {
+                description: None,
+                name: Some(name.into()),
+                parameters: None,
+            },
+        }
+    }
+
+    fn fields(req: RequestBuilder) -> Vec<&'static str> {
+        req.with_messages(messages())
+            .unwrap()
+            .validate()
+            .err()
+            .map(|err| err.violations.iter().map(|v| v.field).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let req = RequestBuilder::new("mixtral-8x7b-32768".into())
+            .with_temperature(50.0)
+            .with_n(0)
+            .with_top_logprobs(3)
+            .with_max_tokens(40_000)
+            .with_tools(vec![tool("a"), tool("a")])
+            .with_tool_choice(tool("b"));
+        assert_eq!(
+            fields(req),
+            vec![
+                "temperature",
+                "n",
+                "max_tokens",
+                "top_logprobs",
+                "tools",
+                "tool_choice"
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_requires_single_choice() {
+        let req = RequestBuilder::new("m".into()).with_stream(true).with_n(2);
+        assert_eq!(fields(req), vec!["n"]);
+        assert!(fields(RequestBuilder::new("m".into()).with_stream(true)).is_empty());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "temperature")]
+    fn build_rejects_invalid_parameters_in_debug_builds() {
+        // the model and messages may still be set later, only the parameters are checked
+        let _ = RequestBuilder::new("".into()).build();
+        let _ = RequestBuilder::new("m".into())
+            .with_temperature(50.0)
+            .build();
+    }
+
+    #[test]
+    fn model_limits_are_optional() {
+        assert!(ModelLimits::for_model("some-new-model").is_none());
+        let req = RequestBuilder::new("some-new-model".into()).with_max_tokens(1_000_000);
+        assert!(fields(req).is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_rejects_invalid_requests_locally() {
+        let transport = std::sync::Arc::new(MockTransport::new());
+        let client = Groq::new("key").with_transport(transport.clone());
+        let err = client
+            .create(RequestBuilder::new("m".into()).with_top_p(3.0), messages())
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<ValidationError>().is_some());
+        assert!(transport.requests().is_empty());
+    }
+}
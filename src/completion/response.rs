@@ -1,7 +1,9 @@
 use chrono::{serde::ts_seconds, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{fmt::Display, hash::Hash};
 
+use super::{logprobs::LogProbs, message::ToolCall};
+
 /// Response object responsible for representing error object returned
 /// # Difference from groq's
 /// - Added Status Code field for convenience
@@ -24,12 +26,15 @@ pub struct ErrorBody {
     #[serde(rename(deserialize = "type"))]
     pub error_type: String,
     pub message: String,
+    /// machine readable error code, e.g. `model_decommissioned` or `context_length_exceeded`
+    #[serde(default)]
+    pub code: Option<String>,
 }
 
 /// Response object responsible for representing completion chunk object returned
 /// # Difference from standard completion object
 /// - The x_groq struct contains the server stream event ID and usage info at the last message
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StreamResponse {
     pub id: String,
     pub object: String,
@@ -53,11 +58,11 @@ impl Hash for StreamResponse {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StreamChoice {
     pub index: u32,
     pub delta: ChoiceDelta,
-    pub logprobs: Option<f32>,
+    pub logprobs: Option<LogProbs>,
     pub finish_reason: Option<String>,
 }
 
@@ -66,27 +71,44 @@ impl Hash for StreamChoice {
         self.index.hash(state);
         self.delta.hash(state);
         self.finish_reason.hash(state);
-
-        if self.logprobs.is_some() {
-            ((self.logprobs.unwrap()) as i32).hash(state); // I understand that this is a little weird, but the logic is that even if we can't hash a float, we can convert it to int and hash that at least.
-        }
+        self.logprobs.hash(state);
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, Hash, Default)]
 pub struct ChoiceDelta {
-    role: Option<String>,
-    content: Option<String>,
+    pub role: Option<String>,
+    pub content: Option<String>,
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
 }
 
-#[derive(Debug, Deserialize, Clone, Hash)]
+/// Fragment of a tool call sent in a stream chunk.
+/// The `index` identifies the tool call the fragment belongs to, the `arguments` of consecutive
+/// fragments are meant to be concatenated.
+#[derive(Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct ToolCallDelta {
+    #[serde(default)]
+    pub index: u32,
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub tool_type: Option<String>,
+    pub function: Option<FunctionDelta>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct FunctionDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Hash)]
 pub struct XGroq {
     pub id: String,
     pub usage: Option<UsageInfo>,
 }
 
 /// Response object responsible for representing completion object returned
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Response {
     pub id: String,
     pub object: String,
@@ -111,7 +133,7 @@ impl Hash for Response {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct UsageInfo {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
@@ -126,18 +148,18 @@ impl Hash for UsageInfo {
         self.prompt_tokens.hash(state);
         self.completion_tokens.hash(state);
         self.total_tokens.hash(state);
-        ((self.prompt_time) as i32).hash(state);
-        ((self.completion_time) as i32).hash(state);
-        ((self.total_time) as i32).hash(state);
+        self.prompt_time.to_bits().hash(state);
+        self.completion_time.to_bits().hash(state);
+        self.total_time.to_bits().hash(state);
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Choice {
     pub index: u32,
     pub message: ChoiceMessage,
     pub finish_reason: String,
-    pub logprobs: Option<f32>,
+    pub logprobs: Option<LogProbs>,
 }
 
 impl Hash for Choice {
@@ -145,14 +167,48 @@ impl Hash for Choice {
         self.index.hash(state);
         self.message.hash(state);
         self.finish_reason.hash(state);
-        if self.logprobs.is_some() {
-            ((self.logprobs.unwrap()) as i32).hash(state);
-        }
+        self.logprobs.hash(state);
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Hash)]
+/// Message of a choice
+/// # Note
+/// - content is empty when the model answered with tool calls only (groq sends `null`)
+#[derive(Debug, Serialize, Deserialize, Clone, Hash)]
 pub struct ChoiceMessage {
     pub role: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod response_test {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use super::UsageInfo;
+
+    #[test]
+    fn usage_hashes_fractional_times() {
+        let hash = |total_time: f32| {
+            let mut hasher = DefaultHasher::new();
+            UsageInfo {
+                total_time,
+                ..Default::default()
+            }
+            .hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_ne!(hash(0.2), hash(0.7));
+        assert_eq!(hash(0.2), hash(0.2));
+    }
 }
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use tokio::{sync::Mutex, time::Instant};
+
+use super::{
+    client::{CompletionOption, Groq},
+    message::Message,
+    request::builder::RequestBuilder,
+    response::ErrorResponse,
+};
+
+/// Exponential backoff applied to failed completion calls.
+/// ```ignore no_run
+/// RetryPolicy {
+///     max_retries: 3,
+///     initial_backoff: Duration::from_millis(500),
+///     max_backoff: Duration::from_secs(8),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        //! A policy that never retries
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        //! Delay before the given retry (starting at 0), doubling every attempt up to `max_backoff`
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    //! Errors worth retrying:
+    //! - 429 Too Many Requests and 5xx answers from groq
+    //! - transport errors that never produced an answer (connection refused or reset,
+    //!   timeouts, ...), unlike builder, redirect or decode errors that fail the same every time
+    if let Some(res) = err.downcast_ref::<ErrorResponse>() {
+        return res.code == reqwest::StatusCode::TOO_MANY_REQUESTS || res.code.is_server_error();
+    }
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(|err| err.is_connect() || err.is_timeout() || err.is_request())
+}
+
+/// Spaces out request starts evenly so that at most `rpm` requests start per minute.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rpm: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(60) / rpm.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub(crate) async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+impl Groq {
+    pub async fn create_with_retry(
+        &self,
+        req: RequestBuilder,
+        messages: Vec<Message>,
+        policy: &RetryPolicy,
+    ) -> anyhow::Result<CompletionOption> {
+        //! Same as [`Groq::create`], retrying errors accepted by [`is_retryable`] according to
+        //! `policy`. The last error is returned once the retries are exhausted.
+        self.create_retrying(&req, &messages, policy, None).await
+    }
+
+    /// Retry loop shared with [`Groq::create_many`], every attempt waits for the limiter first.
+    pub(crate) async fn create_retrying(
+        &self,
+        req: &RequestBuilder,
+        messages: &[Message],
+        policy: &RetryPolicy,
+        limiter: Option<&RateLimiter>,
+    ) -> anyhow::Result<CompletionOption> {
+        let mut attempt = 0;
+        loop {
+            if let Some(limiter) = limiter {
+                limiter.acquire().await;
+            }
+            let res = self
+                .create_attempt(
+                    RequestBuilder::from_builder(req),
+                    messages.to_vec(),
+                    attempt,
+                )
+                .await
+                .map(|timed| timed.completion);
+            match res {
+                Err(err) if attempt < policy.max_retries && is_retryable(&err) => {
+                    tokio::time::sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod retry_test {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(350));
+        assert_eq!(policy.backoff(40), Duration::from_millis(350));
+    }
+}
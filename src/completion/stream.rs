@@ -0,0 +1,318 @@
+//! Bounds on how long a stream completion may run.
+use std::{fmt::Display, time::Duration};
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    response::{ErrorResponse, StreamResponse},
+    retry::is_retryable,
+    transport::HttpRequest,
+};
+
+/// Limits applied to a stream completion, set on the request with
+/// [`RequestBuilder::with_timeout`](super::request::builder::RequestBuilder::with_timeout),
+/// [`RequestBuilder::with_idle_timeout`](super::request::builder::RequestBuilder::with_idle_timeout) and
+/// [`RequestBuilder::with_cancellation`](super::request::builder::RequestBuilder::with_cancellation).
+/// - timeout, upper bound of the whole call, opening the stream included
+/// - idle_timeout, upper bound of the silence between two Server Sent Events
+/// - cancellation, token that stops the call as soon as it is cancelled
+/// - reconnect, how a dropped connection is resumed, set with
+///   [`RequestBuilder::with_reconnect`](super::request::builder::RequestBuilder::with_reconnect)
+///
+/// When a limit is hit the event stream is dropped, which closes the connection, and the call
+/// fails with a [`StreamInterrupted`] holding the chunks received so far.
+#[derive(Debug, Clone, Default)]
+pub struct StreamOptions {
+    pub timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+    pub cancellation: Option<CancellationToken>,
+    pub reconnect: Option<ReconnectPolicy>,
+}
+
+/// Reconnection of a stream completion whose connection dropped before `[DONE]`.
+///
+/// The event source never reconnects on its own since that would submit the completion again
+/// from scratch. With a policy the request is instead sent again with the text received so far
+/// as a trailing assistant message, groq continues the answer from there and the new chunks are
+/// appended to the ones already received, so the caller sees a single stream.
+/// - max_reconnects, how many times the stream may be resumed
+/// - backoff, delay before every reconnection
+///
+/// Only single choice text answers can be resumed, a stream carrying tool calls or several
+/// choices fails with the original error.
+/// ```ignore no_run
+/// ReconnectPolicy {
+///     max_reconnects: 2,
+///     backoff: Duration::from_millis(250),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReconnectPolicy {
+    pub max_reconnects: u32,
+    pub backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_reconnects: 2,
+            backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Request continuing `request` after `chunks`, None when the stream cannot be resumed.
+/// An assistant message already ending the request (a prefill of the caller) is extended rather
+/// than followed by a second one.
+pub(crate) fn resume_request(
+    request: &HttpRequest,
+    chunks: &[StreamResponse],
+) -> Option<HttpRequest> {
+    let mut partial = String::new();
+    for choice in chunks.iter().flat_map(|chunk| &chunk.choices) {
+        if choice.index != 0 || choice.delta.tool_calls.is_some() {
+            return None;
+        }
+        partial.push_str(choice.delta.content.as_deref().unwrap_or_default());
+    }
+
+    let mut resumed = request.clone();
+    if partial.is_empty() {
+        return Some(resumed);
+    }
+    let messages = resumed.body.get_mut("messages")?.as_array_mut()?;
+    match messages.last_mut() {
+        Some(last) if last["role"] == "assistant" && last["content"].is_string() => {
+            let prefill = last["content"].as_str().unwrap_or_default();
+            last["content"] = format!("{}{}", prefill, partial).into();
+        }
+        _ => messages.push(serde_json::json!({
+            "role": "assistant",
+            "content": partial,
+        })),
+    }
+    Some(resumed)
+}
+
+/// Whether a failed reconnection attempt may be followed by another one.
+/// Errors answered by groq are only retried when [`is_retryable`] accepts them.
+pub(crate) fn is_reconnectable(err: &anyhow::Error) -> bool {
+    if err.downcast_ref::<StreamInterrupted>().is_some() {
+        return false;
+    }
+    err.downcast_ref::<ErrorResponse>().is_none() || is_retryable(err)
+}
+
+/// Why a stream completion stopped before groq closed the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterruptReason {
+    Timeout,
+    IdleTimeout,
+    Cancelled,
+}
+
+impl Display for InterruptReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterruptReason::Timeout => write!(f, "timed out"),
+            InterruptReason::IdleTimeout => write!(f, "timed out waiting for the next event"),
+            InterruptReason::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// Error returned by a stream completion stopped by its [`StreamOptions`].
+/// It can be recovered from the `anyhow::Error` with `err.downcast_ref::<StreamInterrupted>()`.
+/// - reason, the limit that was hit
+/// - chunks, the chunks received before the interruption
+#[derive(Debug, Clone)]
+pub struct StreamInterrupted {
+    pub reason: InterruptReason,
+    pub chunks: Vec<StreamResponse>,
+}
+
+impl Display for StreamInterrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "stream completion {} after {} chunks",
+            self.reason,
+            self.chunks.len()
+        )
+    }
+}
+
+impl std::error::Error for StreamInterrupted {}
+
+/// Tracks the limits of a single call while it runs.
+pub(crate) struct StreamGuard {
+    deadline: Option<Instant>,
+    idle_timeout: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl StreamGuard {
+    pub(crate) fn new(options: &StreamOptions) -> Self {
+        Self {
+            deadline: options.timeout.map(|timeout| Instant::now() + timeout),
+            idle_timeout: options.idle_timeout,
+            cancellation: options.cancellation.clone(),
+        }
+    }
+
+    /// Runs `fut` unless a limit is hit first, `idle` tells whether the idle timeout applies.
+    pub(crate) async fn run<F: std::future::Future>(
+        &self,
+        fut: F,
+        idle: bool,
+    ) -> Result<F::Output, InterruptReason> {
+        let cancelled = async {
+            match &self.cancellation {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        let idle_timeout = async {
+            match self.idle_timeout.filter(|_| idle) {
+                Some(idle_timeout) => tokio::time::sleep(idle_timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            biased;
+            _ = cancelled => Err(InterruptReason::Cancelled),
+            _ = deadline => Err(InterruptReason::Timeout),
+            _ = idle_timeout => Err(InterruptReason::IdleTimeout),
+            output = fut => Ok(output),
+        }
+    }
+}
+
+#[cfg(test)]
+mod stream_test {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::{InterruptReason, ReconnectPolicy, StreamInterrupted};
+    use crate::completion::{
+        client::Groq, message::Message, request::builder::RequestBuilder, transport::MockTransport,
+    };
+
+    fn chunk(content: &str) -> String {
+        format!(
+            r#"{{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{{"index":0,"delta":{{"content":"{}"}},"logprobs":null,"finish_reason":null}}],"x_groq":{{"id":"req_1"}}}}"#,
+            content
+        )
+    }
+
+    fn messages() -> Vec<Message> {
+        vec![Message::UserMessage {
+            role: Some("user".to_string()),
+            content: Some("hello".to_string()),
+            name: None,
+            tool_call_id: None,
+        }]
+    }
+
+    fn interruption(res: anyhow::Result<impl std::fmt::Debug>) -> StreamInterrupted {
+        res.unwrap_err()
+            .downcast::<StreamInterrupted>()
+            .expect("a StreamInterrupted error")
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_returns_partial_chunks() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_stalled_events(vec![chunk("Hel"), chunk("lo")]);
+        let client = Groq::new("key").with_transport(transport);
+
+        let req = RequestBuilder::new("m".into())
+            .with_stream(true)
+            .with_idle_timeout(Duration::from_millis(50));
+        let err = interruption(client.create(req, messages()).await);
+        assert_eq!(err.reason, InterruptReason::IdleTimeout);
+        assert_eq!(err.chunks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn total_timeout_and_cancellation() {
+        let transport = Arc::new(MockTransport::new());
+        transport
+            .push_stalled_events(vec![chunk("Hel")])
+            .push_stalled_events(vec![]);
+        let client = Groq::new("key").with_transport(transport);
+
+        let req = RequestBuilder::new("m".into())
+            .with_stream(true)
+            .with_timeout(Duration::from_millis(100));
+        let err = interruption(client.create(req, messages()).await);
+        assert_eq!(err.reason, InterruptReason::Timeout);
+        assert_eq!(err.chunks.len(), 1);
+
+        let token = CancellationToken::new();
+        let req = RequestBuilder::new("m".into())
+            .with_stream(true)
+            .with_cancellation(token.clone());
+        let canceller = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            token.cancel();
+        });
+        let err = interruption(client.create(req, messages()).await);
+        assert_eq!(err.reason, InterruptReason::Cancelled);
+        assert!(err.chunks.is_empty());
+        canceller.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropped_stream_resumes_with_prefill() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        // the first connection closes without [DONE]
+        transport
+            .push_events(vec![chunk("Hel"), chunk("lo")])
+            .push_events(vec![chunk(" world"), "[DONE]".to_string()]);
+        let client = Groq::new("key").with_transport(transport.clone());
+
+        let req = RequestBuilder::new("m".into())
+            .with_stream(true)
+            .with_reconnect(ReconnectPolicy {
+                max_reconnects: 1,
+                backoff: Duration::ZERO,
+            });
+        let res = client.create(req, messages()).await?.into_response()?;
+        assert_eq!(res.choices[0].message.content, "Hello world");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        let resumed = requests[1].body["messages"].as_array().unwrap();
+        assert_eq!(resumed.len(), 2);
+        assert_eq!(resumed[1]["role"], "assistant");
+        assert_eq!(resumed[1]["content"], "Hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reconnects_are_bounded() {
+        let transport = Arc::new(MockTransport::new());
+        transport
+            .push_events(vec![chunk("Hel")])
+            .push_events(vec![chunk("lo")]);
+        let client = Groq::new("key").with_transport(transport.clone());
+
+        let req = RequestBuilder::new("m".into())
+            .with_stream(true)
+            .with_reconnect(ReconnectPolicy {
+                max_reconnects: 1,
+                backoff: Duration::ZERO,
+            });
+        assert!(client.create(req, messages()).await.is_err());
+        assert_eq!(transport.requests().len(), 2);
+    }
+}
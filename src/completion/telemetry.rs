@@ -0,0 +1,278 @@
+//! OpenTelemetry integration following the
+//! [GenAI semantic conventions](https://opentelemetry.io/docs/specs/semconv/gen-ai/).
+//!
+//! With the `opentelemetry` feature enabled every call to
+//! [`Groq::create`](super::client::Groq::create) emits a `chat {model}` client span and records the
+//! metrics below through the global providers of [`opentelemetry::global`].
+//! The crate never installs a provider, so nothing is exported until the application sets one up.
+use std::{sync::OnceLock, time::Duration};
+
+use opentelemetry::{
+    global,
+    metrics::{Counter, Histogram},
+    trace::{Span, SpanKind, Status, Tracer},
+    Array, KeyValue, StringValue, Value,
+};
+
+use super::{
+    client::CompletionOption,
+    request::builder::RequestBuilder,
+    response::{ErrorResponse, UsageInfo},
+};
+
+/// Name of the meter and tracer used by the crate
+pub const INSTRUMENTATION_SCOPE: &str = "groq-api-rs";
+/// Value of `gen_ai.system` / `gen_ai.provider.name`
+pub const SYSTEM: &str = "groq";
+
+/// Histogram of the wall clock duration of a completion call, in seconds
+pub const OPERATION_DURATION: &str = "gen_ai.client.operation.duration";
+/// Histogram of the tokens used per call, split by `gen_ai.token.type` (`input` / `output`)
+pub const TOKEN_USAGE: &str = "gen_ai.client.token.usage";
+/// Histogram of the time until the first chunk of a stream completion arrived, in seconds
+pub const TIME_TO_FIRST_CHUNK: &str = "gen_ai.client.operation.time_to_first_chunk";
+/// Counter of failed completion calls, split by `error.type`
+pub const OPERATION_ERRORS: &str = "groq.client.operation.errors";
+
+struct Instruments {
+    duration: Histogram<f64>,
+    token_usage: Histogram<u64>,
+    time_to_first_chunk: Histogram<f64>,
+    errors: Counter<u64>,
+}
+
+fn instruments() -> &'static Instruments {
+    static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+    INSTRUMENTS.get_or_init(|| {
+        let meter = global::meter(INSTRUMENTATION_SCOPE);
+        Instruments {
+            duration: meter
+                .f64_histogram(OPERATION_DURATION)
+                .with_unit("s")
+                .with_description("GenAI operation duration")
+                .build(),
+            token_usage: meter
+                .u64_histogram(TOKEN_USAGE)
+                .with_unit("{token}")
+                .with_description("Measures number of input and output tokens used")
+                .build(),
+            time_to_first_chunk: meter
+                .f64_histogram(TIME_TO_FIRST_CHUNK)
+                .with_unit("s")
+                .with_description("Time to receive the first chunk of a streamed completion")
+                .build(),
+            errors: meter
+                .u64_counter(OPERATION_ERRORS)
+                .with_description("Number of failed completion calls")
+                .build(),
+        }
+    })
+}
+
+/// Span and metric attributes of a single completion call.
+pub(crate) struct OtelCall {
+    span: global::BoxedSpan,
+    model: String,
+}
+
+impl OtelCall {
+    pub(crate) fn start(req: &RequestBuilder) -> Self {
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.operation.name", "chat"),
+            KeyValue::new("gen_ai.system", SYSTEM),
+            KeyValue::new("gen_ai.provider.name", SYSTEM),
+            KeyValue::new("gen_ai.request.model", req.model().to_string()),
+            KeyValue::new("gen_ai.request.temperature", req.temperature() as f64),
+            KeyValue::new("gen_ai.request.top_p", req.top_p() as f64),
+            KeyValue::new("server.address", "api.groq.com"),
+            KeyValue::new("groq.request.stream", req.is_stream()),
+        ];
+        if let Some(max_tokens) = req.max_tokens() {
+            attributes.push(KeyValue::new(
+                "gen_ai.request.max_tokens",
+                max_tokens as i64,
+            ));
+        }
+        let tracer = global::tracer(INSTRUMENTATION_SCOPE);
+        let span = tracer
+            .span_builder(format!("chat {}", req.model()))
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes)
+            .start(&tracer);
+        Self {
+            span,
+            model: req.model().to_string(),
+        }
+    }
+
+    pub(crate) fn finish(
+        mut self,
+        duration: Duration,
+        first_chunk: Option<Duration>,
+        status: Option<u16>,
+        result: Result<&CompletionOption, &anyhow::Error>,
+    ) {
+        let instruments = instruments();
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.operation.name", "chat"),
+            KeyValue::new("gen_ai.system", SYSTEM),
+            KeyValue::new("gen_ai.request.model", self.model.clone()),
+        ];
+        if let Some(status) = status {
+            self.span
+                .set_attribute(KeyValue::new("http.response.status_code", status as i64));
+        }
+
+        match result {
+            Ok(completion) => {
+                let summary = Summary::from(completion);
+                if let Some(model) = &summary.model {
+                    attributes.push(KeyValue::new("gen_ai.response.model", model.clone()));
+                }
+                self.span.set_attributes(summary.attributes());
+                if let Some(usage) = summary.usage {
+                    for (token_type, count) in [
+                        ("input", usage.prompt_tokens),
+                        ("output", usage.completion_tokens),
+                    ] {
+                        let mut token_attributes = attributes.clone();
+                        token_attributes.push(KeyValue::new("gen_ai.token.type", token_type));
+                        instruments
+                            .token_usage
+                            .record(count as u64, &token_attributes);
+                    }
+                }
+            }
+            Err(err) => {
+                let error_type = error_type(err);
+                attributes.push(KeyValue::new("error.type", error_type.clone()));
+                self.span
+                    .set_attribute(KeyValue::new("error.type", error_type));
+                self.span.set_status(Status::error(err.to_string()));
+                instruments.errors.add(1, &attributes);
+            }
+        }
+
+        instruments
+            .duration
+            .record(duration.as_secs_f64(), &attributes);
+        if let Some(first_chunk) = first_chunk {
+            instruments
+                .time_to_first_chunk
+                .record(first_chunk.as_secs_f64(), &attributes);
+        }
+        self.span.end();
+    }
+}
+
+/// `error.type` of a failed call, groq's error type when the API answered with an error object.
+fn error_type(err: &anyhow::Error) -> String {
+    match err.downcast_ref::<ErrorResponse>() {
+        Some(res) => res.error.error_type.clone(),
+        None => "_OTHER".to_string(),
+    }
+}
+
+/// Response side attributes, gathered from either a full response or the stream chunks.
+struct Summary {
+    id: Option<String>,
+    model: Option<String>,
+    finish_reasons: Vec<String>,
+    usage: Option<UsageInfo>,
+    x_groq_id: Option<String>,
+}
+
+impl From<&CompletionOption> for Summary {
+    fn from(completion: &CompletionOption) -> Self {
+        match completion {
+            CompletionOption::NonStream(res) => Self {
+                id: Some(res.id.clone()),
+                model: Some(res.model.clone()),
+                finish_reasons: res
+                    .choices
+                    .iter()
+                    .map(|choice| choice.finish_reason.clone())
+                    .collect(),
+                usage: Some(res.usage.clone()),
+                x_groq_id: None,
+            },
+            CompletionOption::Stream(chunks) => Self {
+                id: chunks.first().map(|chunk| chunk.id.clone()),
+                model: chunks.first().map(|chunk| chunk.model.clone()),
+                finish_reasons: chunks
+                    .iter()
+                    .flat_map(|chunk| &chunk.choices)
+                    .filter_map(|choice| choice.finish_reason.clone())
+                    .collect(),
+                usage: chunks
+                    .iter()
+                    .rev()
+                    .find_map(|chunk| chunk.x_groq.as_ref()?.usage.clone()),
+                x_groq_id: chunks
+                    .iter()
+                    .find_map(|chunk| Some(chunk.x_groq.as_ref()?.id.clone())),
+            },
+        }
+    }
+}
+
+impl Summary {
+    fn attributes(&self) -> Vec<KeyValue> {
+        let mut attributes = Vec::new();
+        if let Some(id) = &self.id {
+            attributes.push(KeyValue::new("gen_ai.response.id", id.clone()));
+        }
+        if let Some(model) = &self.model {
+            attributes.push(KeyValue::new("gen_ai.response.model", model.clone()));
+        }
+        if !self.finish_reasons.is_empty() {
+            attributes.push(KeyValue::new(
+                "gen_ai.response.finish_reasons",
+                Value::Array(Array::String(
+                    self.finish_reasons
+                        .iter()
+                        .cloned()
+                        .map(StringValue::from)
+                        .collect(),
+                )),
+            ));
+        }
+        if let Some(usage) = &self.usage {
+            attributes.push(KeyValue::new(
+                "gen_ai.usage.input_tokens",
+                usage.prompt_tokens as i64,
+            ));
+            attributes.push(KeyValue::new(
+                "gen_ai.usage.output_tokens",
+                usage.completion_tokens as i64,
+            ));
+        }
+        if let Some(id) = &self.x_groq_id {
+            attributes.push(KeyValue::new("groq.x_groq.id", id.clone()));
+        }
+        attributes
+    }
+}
+
+#[cfg(test)]
+mod telemetry_test {
+    use super::Summary;
+    use crate::completion::client::CompletionOption;
+
+    #[test]
+    fn stream_summary_takes_usage_from_x_groq() -> anyhow::Result<()> {
+        let chunks = vec![
+            serde_json::from_str(
+                r#"{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{"index":0,"delta":{"content":"hi"},"logprobs":null,"finish_reason":null}],"x_groq":{"id":"req_1"}}"#,
+            )?,
+            serde_json::from_str(
+                r#"{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{"index":0,"delta":{},"logprobs":null,"finish_reason":"stop"}],"x_groq":{"id":"req_1","usage":{"prompt_tokens":3,"completion_tokens":1,"total_tokens":4,"prompt_time":0.1,"completion_time":0.1,"total_time":0.2}}}"#,
+            )?,
+        ];
+        let summary = Summary::from(&CompletionOption::Stream(chunks));
+        assert_eq!(summary.finish_reasons, vec!["stop".to_string()]);
+        assert_eq!(summary.usage.map(|usage| usage.prompt_tokens), Some(3));
+        assert_eq!(summary.x_groq_id.as_deref(), Some("req_1"));
+        Ok(())
+    }
+}
@@ -0,0 +1,628 @@
+//! Multi message prompt templates rendering to `Vec<Message>`.
+//!
+//! A template is a list of turns (system, user or assistant) whose text may contain
+//! - `{{name}}`, replaced by the value of the variable `name`
+//! - `{{> partial}}`, replaced by the partial registered under that name (partials may use
+//!   variables and other partials)
+//! - `{{#if name}} ... {{else}} ... {{/if}}` and `{{#unless name}} ... {{/unless}}`, kept or
+//!   dropped depending on whether `name` is truthy (true, a non empty text or list, a non zero
+//!   number)
+//!
+//! ```ignore no_run
+//! let support = PromptTemplate::new()
+//!     .with_partial("tone", "Answer in a {{tone}} tone.")?
+//!     .with_system("You are a support agent for {{product}}. {{> tone}}")?
+//!     .with_user("{{#if order_id}}About order {{order_id}}: {{/if}}{{question}}")?
+//!     .with_optional("order_id");
+//!
+//! let messages = support.render(
+//!     &Vars::new()
+//!         .with("product", "Acme")
+//!         .with("tone", "friendly")
+//!         .with("question", "Where is my parcel?"),
+//! )?;
+//! conversation.add_messages(messages);
+//! ```
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+};
+
+use super::message::Message;
+
+/// How deep partials may include each other, guards against cycles
+const MAX_PARTIAL_DEPTH: usize = 16;
+
+/// Value of a template variable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateValue {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    /// rendered as its items joined by ", "
+    List(Vec<String>),
+}
+
+/// Kind of a [`TemplateValue`], used to declare the expected type of a variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueKind {
+    Text,
+    Number,
+    Bool,
+    List,
+}
+
+impl TemplateValue {
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            TemplateValue::Text(_) => ValueKind::Text,
+            TemplateValue::Number(_) => ValueKind::Number,
+            TemplateValue::Bool(_) => ValueKind::Bool,
+            TemplateValue::List(_) => ValueKind::List,
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            TemplateValue::Text(text) => !text.is_empty(),
+            TemplateValue::Number(n) => *n != 0.0,
+            TemplateValue::Bool(b) => *b,
+            TemplateValue::List(items) => !items.is_empty(),
+        }
+    }
+}
+
+impl Display for TemplateValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateValue::Text(text) => write!(f, "{}", text),
+            TemplateValue::Number(n) => write!(f, "{}", n),
+            TemplateValue::Bool(b) => write!(f, "{}", b),
+            TemplateValue::List(items) => write!(f, "{}", items.join(", ")),
+        }
+    }
+}
+
+impl From<&str> for TemplateValue {
+    fn from(value: &str) -> Self {
+        TemplateValue::Text(value.into())
+    }
+}
+
+impl From<String> for TemplateValue {
+    fn from(value: String) -> Self {
+        TemplateValue::Text(value)
+    }
+}
+
+impl From<bool> for TemplateValue {
+    fn from(value: bool) -> Self {
+        TemplateValue::Bool(value)
+    }
+}
+
+impl From<f64> for TemplateValue {
+    fn from(value: f64) -> Self {
+        TemplateValue::Number(value)
+    }
+}
+
+impl From<i64> for TemplateValue {
+    fn from(value: i64) -> Self {
+        TemplateValue::Number(value as f64)
+    }
+}
+
+impl From<Vec<String>> for TemplateValue {
+    fn from(value: Vec<String>) -> Self {
+        TemplateValue::List(value)
+    }
+}
+
+/// Variables supplied to [`PromptTemplate::render`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vars {
+    values: BTreeMap<String, TemplateValue>,
+}
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<TemplateValue>) -> Self {
+        self.values.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn set(&mut self, name: &str, value: impl Into<TemplateValue>) {
+        //! Non Consuming
+        self.values.insert(name.into(), value.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TemplateValue> {
+        self.values.get(name)
+    }
+}
+
+/// Errors of parsing or rendering a template.
+/// - Parse, the text of a turn or partial is malformed
+/// - MissingVariables, required variables without a value, all of them
+/// - WrongType, a variable does not have the declared kind
+/// - UnknownPartial, a `{{> name}}` without a registered partial
+/// - PartialDepth, partials nested deeper than 16 levels, most likely a cycle
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    Parse {
+        source: String,
+        message: String,
+    },
+    MissingVariables(Vec<String>),
+    WrongType {
+        name: String,
+        expected: ValueKind,
+        found: ValueKind,
+    },
+    UnknownPartial(String),
+    PartialDepth(String),
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::Parse { source, message } => {
+                write!(f, "invalid template '{}': {}", source, message)
+            }
+            TemplateError::MissingVariables(names) => {
+                write!(f, "missing template variables: {}", names.join(", "))
+            }
+            TemplateError::WrongType {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "template variable '{}' should be {:?} but is {:?}",
+                name, expected, found
+            ),
+            TemplateError::UnknownPartial(name) => write!(f, "unknown partial '{}'", name),
+            TemplateError::PartialDepth(name) => {
+                write!(f, "partial '{}' is nested too deep, is it recursive?", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    Partial(String),
+    If {
+        name: String,
+        negate: bool,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Turn {
+    role: Role,
+    nodes: Vec<Node>,
+}
+
+/// Prompt made of several turns with named placeholders, see the [module docs](self) for the
+/// syntax. A base template holding a shared system prompt can be cloned and extended with
+/// different user turns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptTemplate {
+    turns: Vec<Turn>,
+    partials: HashMap<String, Vec<Node>>,
+    optional: BTreeSet<String>,
+    kinds: BTreeMap<String, ValueKind>,
+}
+
+impl PromptTemplate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_turn(mut self, role: Role, text: &str) -> Result<Self, TemplateError> {
+        self.turns.push(Turn {
+            role,
+            nodes: parse(text)?,
+        });
+        Ok(self)
+    }
+
+    pub fn with_system(self, text: &str) -> Result<Self, TemplateError> {
+        self.with_turn(Role::System, text)
+    }
+
+    pub fn with_user(self, text: &str) -> Result<Self, TemplateError> {
+        self.with_turn(Role::User, text)
+    }
+
+    pub fn with_assistant(self, text: &str) -> Result<Self, TemplateError> {
+        //! An assistant turn, e.g. the answer of a few-shot example
+        self.with_turn(Role::Assistant, text)
+    }
+
+    pub fn with_example(self, user: &str, assistant: &str) -> Result<Self, TemplateError> {
+        //! A few-shot example, a user turn followed by the expected assistant answer
+        self.with_user(user)?.with_assistant(assistant)
+    }
+
+    pub fn with_partial(mut self, name: &str, text: &str) -> Result<Self, TemplateError> {
+        self.partials.insert(name.into(), parse(text)?);
+        Ok(self)
+    }
+
+    pub fn with_optional(mut self, name: &str) -> Self {
+        //! Lets `name` be left out at render time, it then renders as an empty text and is falsy
+        self.optional.insert(name.into());
+        self
+    }
+
+    pub fn with_kind(mut self, name: &str, kind: ValueKind) -> Self {
+        //! Declares the kind of value `name` must be given
+        self.kinds.insert(name.into(), kind);
+        self
+    }
+
+    pub fn variables(&self) -> Result<BTreeSet<String>, TemplateError> {
+        //! Every variable used by the turns, through partials and both branches of conditionals
+        let mut names = BTreeSet::new();
+        for turn in &self.turns {
+            self.collect(&turn.nodes, &mut names, 0)?;
+        }
+        Ok(names)
+    }
+
+    fn collect(
+        &self,
+        nodes: &[Node],
+        names: &mut BTreeSet<String>,
+        depth: usize,
+    ) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(_) => {}
+                Node::Var(name) => {
+                    names.insert(name.clone());
+                }
+                Node::Partial(name) => {
+                    self.collect(self.partial(name, depth)?, names, depth + 1)?;
+                }
+                Node::If {
+                    name,
+                    then,
+                    otherwise,
+                    ..
+                } => {
+                    names.insert(name.clone());
+                    self.collect(then, names, depth)?;
+                    self.collect(otherwise, names, depth)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn partial(&self, name: &str, depth: usize) -> Result<&[Node], TemplateError> {
+        if depth >= MAX_PARTIAL_DEPTH {
+            return Err(TemplateError::PartialDepth(name.into()));
+        }
+        self.partials
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| TemplateError::UnknownPartial(name.into()))
+    }
+
+    pub fn validate(&self, vars: &Vars) -> Result<(), TemplateError> {
+        //! Checks that every required variable is supplied and has its declared kind
+        let missing: Vec<String> = self
+            .variables()?
+            .into_iter()
+            .filter(|name| vars.get(name).is_none() && !self.optional.contains(name))
+            .collect();
+        if !missing.is_empty() {
+            return Err(TemplateError::MissingVariables(missing));
+        }
+        for (name, expected) in &self.kinds {
+            if let Some(value) = vars.get(name) {
+                if value.kind() != *expected {
+                    return Err(TemplateError::WrongType {
+                        name: name.clone(),
+                        expected: *expected,
+                        found: value.kind(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, vars: &Vars) -> Result<Vec<Message>, TemplateError> {
+        //! Renders every turn to a message, after [`PromptTemplate::validate`].
+        //! The text of every message is trimmed and turns rendering to nothing are left out.
+        self.validate(vars)?;
+        let mut messages = Vec::with_capacity(self.turns.len());
+        for turn in &self.turns {
+            let mut out = String::new();
+            self.render_nodes(&turn.nodes, vars, &mut out, 0)?;
+            let content = out.trim();
+            if !content.is_empty() {
+                messages.push(message(turn.role, content.to_string()));
+            }
+        }
+        Ok(messages)
+    }
+
+    fn render_nodes(
+        &self,
+        nodes: &[Node],
+        vars: &Vars,
+        out: &mut String,
+        depth: usize,
+    ) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Var(name) => {
+                    if let Some(value) = vars.get(name) {
+                        out.push_str(&value.to_string());
+                    }
+                }
+                Node::Partial(name) => {
+                    self.render_nodes(self.partial(name, depth)?, vars, out, depth + 1)?
+                }
+                Node::If {
+                    name,
+                    negate,
+                    then,
+                    otherwise,
+                } => {
+                    let truthy = vars.get(name).is_some_and(TemplateValue::is_truthy);
+                    let branch = if truthy != *negate { then } else { otherwise };
+                    self.render_nodes(branch, vars, out, depth)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn message(role: Role, content: String) -> Message {
+    match role {
+        Role::System => Message::SystemMessage {
+            content: Some(content),
+            name: None,
+            role: Some("system".to_string()),
+            tool_call_id: None,
+        },
+        Role::User => Message::UserMessage {
+            content: Some(content),
+            name: None,
+            role: Some("user".to_string()),
+            tool_call_id: None,
+        },
+        Role::Assistant => Message::AssistantMessage {
+            content: Some(content),
+            name: None,
+            role: Some("assistant".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+        },
+    }
+}
+
+/// An `{{#if}}` / `{{#unless}}` block being parsed
+struct Block {
+    name: String,
+    negate: bool,
+    then: Vec<Node>,
+    in_else: bool,
+}
+
+fn parse(source: &str) -> Result<Vec<Node>, TemplateError> {
+    let error = |message: String| TemplateError::Parse {
+        source: source.to_string(),
+        message,
+    };
+    let mut stack: Vec<Block> = Vec::new();
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut rest = source;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| error("unclosed '{{'".into()))?;
+        let tag = rest[start + 2..start + end].trim();
+        rest = &rest[start + end + 2..];
+
+        if let Some(name) = tag.strip_prefix("#if ") {
+            stack.push(Block {
+                name: identifier(name.trim()).map_err(&error)?,
+                negate: false,
+                then: std::mem::take(&mut nodes),
+                in_else: false,
+            });
+        } else if let Some(name) = tag.strip_prefix("#unless ") {
+            stack.push(Block {
+                name: identifier(name.trim()).map_err(&error)?,
+                negate: true,
+                then: std::mem::take(&mut nodes),
+                in_else: false,
+            });
+        } else if tag == "else" {
+            let block = stack
+                .last_mut()
+                .filter(|block| !block.in_else)
+                .ok_or_else(|| error("'{{else}}' outside of a conditional".into()))?;
+            // `then` holds the enclosing nodes until the block is closed
+            std::mem::swap(&mut block.then, &mut nodes);
+            block.in_else = true;
+            // now `nodes` are the enclosing nodes, park them after the then branch
+            let enclosing = std::mem::take(&mut nodes);
+            stack.push(Block {
+                name: String::new(),
+                negate: false,
+                then: enclosing,
+                in_else: true,
+            });
+        } else if tag == "/if" || tag == "/unless" {
+            let negate = tag == "/unless";
+            let (block, then, otherwise) = match stack.pop() {
+                // closing an `{{else}}` branch, see above for the layout of the stack
+                Some(parked) if parked.name.is_empty() => {
+                    let block = stack
+                        .pop()
+                        .ok_or_else(|| error(format!("unexpected '{{{{{}}}}}'", tag)))?;
+                    let otherwise = std::mem::replace(&mut nodes, parked.then);
+                    let then = block.then.clone();
+                    (block, then, otherwise)
+                }
+                Some(block) => {
+                    let then = std::mem::replace(&mut nodes, block.then.clone());
+                    (block, then, Vec::new())
+                }
+                None => return Err(error(format!("unexpected '{{{{{}}}}}'", tag))),
+            };
+            if block.negate != negate {
+                return Err(error(format!(
+                    "'{{{{{}}}}}' closes a block opened for '{}'",
+                    tag, block.name
+                )));
+            }
+            nodes.push(Node::If {
+                name: block.name,
+                negate,
+                then,
+                otherwise,
+            });
+        } else if let Some(name) = tag.strip_prefix('>') {
+            nodes.push(Node::Partial(identifier(name.trim()).map_err(&error)?));
+        } else {
+            nodes.push(Node::Var(identifier(tag).map_err(&error)?));
+        }
+    }
+    if !rest.is_empty() {
+        nodes.push(Node::Text(rest.to_string()));
+    }
+    if let Some(block) = stack.iter().rev().find(|block| !block.name.is_empty()) {
+        return Err(error(format!("unclosed block '{}'", block.name)));
+    }
+    Ok(nodes)
+}
+
+fn identifier(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!("'{}' is not a valid name", name))
+    }
+}
+
+#[cfg(test)]
+mod template_test {
+    use super::{PromptTemplate, TemplateError, ValueKind, Vars};
+    use crate::completion::message::Message;
+
+    fn support() -> Result<PromptTemplate, TemplateError> {
+        Ok(PromptTemplate::new()
+            .with_partial("tone", "Answer in a {{tone}} tone.")?
+            .with_system("You are a support agent for {{product}}. {{> tone}}")?
+            .with_example("Is it waterproof?", "Yes, up to {{depth}} meters.")?
+            .with_user(
+                "{{#if order_id}}About order {{order_id}}: {{else}}No order. {{/if}}{{question}}",
+            )?
+            .with_assistant("{{#unless draft}}{{/unless}}")?
+            .with_optional("order_id")
+            .with_optional("draft")
+            .with_kind("depth", ValueKind::Number))
+    }
+
+    fn vars() -> Vars {
+        Vars::new()
+            .with("product", "Acme")
+            .with("tone", "friendly")
+            .with("depth", 10i64)
+            .with("question", "Where is my parcel?")
+    }
+
+    #[test]
+    fn renders_turns_partials_and_conditionals() -> anyhow::Result<()> {
+        let messages = support()?.render(&vars().with("order_id", "A-42"))?;
+        let contents: Vec<_> = messages.iter().filter_map(Message::content).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "You are a support agent for Acme. Answer in a friendly tone.",
+                "Is it waterproof?",
+                "Yes, up to 10 meters.",
+                "About order A-42: Where is my parcel?",
+            ]
+        );
+        assert!(matches!(messages[0], Message::SystemMessage { .. }));
+        assert!(matches!(messages[2], Message::AssistantMessage { .. }));
+
+        let messages = support()?.render(&vars())?;
+        assert_eq!(messages[3].content(), Some("No order. Where is my parcel?"));
+        Ok(())
+    }
+
+    #[test]
+    fn reports_missing_and_mistyped_variables() -> anyhow::Result<()> {
+        let err = support()?
+            .render(&Vars::new().with("tone", "dry"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            TemplateError::MissingVariables(vec![
+                "depth".into(),
+                "product".into(),
+                "question".into()
+            ])
+        );
+        let err = support()?
+            .render(&vars().with("depth", "deep"))
+            .unwrap_err();
+        assert!(matches!(err, TemplateError::WrongType { .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(PromptTemplate::new().with_user("{{#if a}}open").is_err());
+        assert!(PromptTemplate::new()
+            .with_user("{{#if a}}x{{/unless}}")
+            .is_err());
+        assert!(PromptTemplate::new().with_user("{{bad name}}").is_err());
+        let looping = PromptTemplate::new()
+            .with_partial("a", "{{> a}}")
+            .and_then(|template| template.with_user("{{> a}}"))
+            .unwrap();
+        assert!(matches!(
+            looping.render(&Vars::new()),
+            Err(TemplateError::PartialDepth(_))
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! Client side timings of completion calls.
+use std::time::Duration;
+
+use super::{
+    client::{CompletionOption, Groq},
+    message::Message,
+    request::builder::RequestBuilder,
+    response::StreamResponse,
+};
+
+/// Timings measured by the client, as opposed to the server reported [`UsageInfo`](super::response::UsageInfo) times.
+/// - latency, wall clock duration of the whole call, from sending the request to the last chunk
+/// - time_to_first_token, time until the first chunk of a stream carrying content or a tool call
+///   arrived, the leading role only chunk is not a token (None for non stream calls)
+/// - inter_token_gaps, time between consecutive chunks of a stream carrying content or a tool call
+///   (empty for non stream calls)
+/// - completion_tokens, tokens generated, taken from the usage info or counted from the chunks
+/// - tokens_per_second, completion tokens over the generation time (latency minus time to first token)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClientTimings {
+    pub latency: Duration,
+    pub time_to_first_token: Option<Duration>,
+    pub inter_token_gaps: Vec<Duration>,
+    pub completion_tokens: Option<u32>,
+    pub tokens_per_second: Option<f64>,
+}
+
+impl ClientTimings {
+    /// Builds the timings from the arrival time of every chunk carrying tokens, relative to the
+    /// start of the call.
+    pub(crate) fn measure(
+        latency: Duration,
+        token_arrivals: &[Duration],
+        completion: &CompletionOption,
+    ) -> Self {
+        let completion_tokens = match completion {
+            CompletionOption::NonStream(res) => Some(res.usage.completion_tokens),
+            CompletionOption::Stream(chunks) => chunks
+                .iter()
+                .rev()
+                .find_map(|chunk| Some(chunk.x_groq.as_ref()?.usage.as_ref()?.completion_tokens))
+                .or_else(|| {
+                    // without usage info every chunk carrying tokens is counted as one token
+                    let count = chunks.iter().filter(|chunk| chunk.carries_tokens()).count();
+                    u32::try_from(count).ok()
+                }),
+        };
+        let time_to_first_token = token_arrivals.first().copied();
+        let generation = latency.saturating_sub(time_to_first_token.unwrap_or_default());
+        let tokens_per_second = completion_tokens
+            .filter(|_| !generation.is_zero())
+            .map(|tokens| tokens as f64 / generation.as_secs_f64());
+
+        Self {
+            latency,
+            time_to_first_token,
+            inter_token_gaps: token_arrivals
+                .windows(2)
+                .map(|pair| pair[1].saturating_sub(pair[0]))
+                .collect(),
+            completion_tokens,
+            tokens_per_second,
+        }
+    }
+
+    pub fn mean_inter_token_gap(&self) -> Option<Duration> {
+        //! Average of `inter_token_gaps`, None when less than two chunks were received
+        let count = u32::try_from(self.inter_token_gaps.len()).ok()?;
+        if count == 0 {
+            return None;
+        }
+        Some(self.inter_token_gaps.iter().sum::<Duration>() / count)
+    }
+
+    pub fn max_inter_token_gap(&self) -> Option<Duration> {
+        self.inter_token_gaps.iter().max().copied()
+    }
+}
+
+impl StreamResponse {
+    pub(crate) fn carries_tokens(&self) -> bool {
+        //! Whether a choice of the chunk has content or a tool call fragment, groq opens a stream
+        //! with a chunk carrying only the role and an empty content
+        self.choices.iter().any(|choice| {
+            let delta = &choice.delta;
+            delta
+                .content
+                .as_ref()
+                .is_some_and(|content| !content.is_empty())
+                || delta
+                    .tool_calls
+                    .as_ref()
+                    .is_some_and(|calls| !calls.is_empty())
+        })
+    }
+}
+
+/// A completion together with the [`ClientTimings`] of the call that produced it.
+#[derive(Debug, Clone)]
+pub struct TimedCompletion {
+    pub completion: CompletionOption,
+    pub timings: ClientTimings,
+}
+
+impl Groq {
+    pub async fn create_with_timings(
+        &self,
+        req: RequestBuilder,
+        messages: Vec<Message>,
+    ) -> anyhow::Result<TimedCompletion> {
+        //! Same as [`Groq::create`], additionally returning the [`ClientTimings`] of the call.
+        //! With a [`FallbackPolicy`](super::fallback::FallbackPolicy) the timings are those of
+        //! the call of the model that answered.
+        self.create_attempt(req, messages, 0).await
+    }
+}
+
+#[cfg(test)]
+mod timings_test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::ClientTimings;
+    use crate::completion::{
+        client::{CompletionOption, Groq},
+        fallback::FallbackPolicy,
+        request::builder::RequestBuilder,
+        transport::{fixtures, MockTransport},
+    };
+
+    #[test]
+    fn measures_gaps_and_throughput() -> anyhow::Result<()> {
+        let chunks = vec![
+            serde_json::from_str(
+                r#"{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{"index":0,"delta":{"content":"hi"},"logprobs":null,"finish_reason":null}],"x_groq":{"id":"req_1"}}"#,
+            )?,
+            serde_json::from_str(
+                r#"{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{"index":0,"delta":{},"logprobs":null,"finish_reason":"stop"}],"x_groq":{"id":"req_1","usage":{"prompt_tokens":3,"completion_tokens":10,"total_tokens":13,"prompt_time":0.1,"completion_time":0.1,"total_time":0.2}}}"#,
+            )?,
+        ];
+        let arrivals = [
+            Duration::from_millis(100),
+            Duration::from_millis(150),
+            Duration::from_millis(300),
+        ];
+        let timings = ClientTimings::measure(
+            Duration::from_millis(600),
+            &arrivals,
+            &CompletionOption::Stream(chunks),
+        );
+
+        assert_eq!(
+            timings.time_to_first_token,
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            timings.inter_token_gaps,
+            vec![Duration::from_millis(50), Duration::from_millis(150)]
+        );
+        assert_eq!(
+            timings.mean_inter_token_gap(),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            timings.max_inter_token_gap(),
+            Some(Duration::from_millis(150))
+        );
+        assert_eq!(timings.completion_tokens, Some(10));
+        // 10 tokens over the 500ms following the first token
+        assert_eq!(timings.tokens_per_second, Some(20.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn role_only_chunks_are_not_tokens() -> anyhow::Result<()> {
+        let chunk = |delta: &str| {
+            format!(
+                r#"{{"id":"1","object":"chat.completion.chunk","created":1718000000,"model":"m","system_fingerprint":null,"choices":[{{"index":0,"delta":{},"logprobs":null,"finish_reason":null}}],"x_groq":{{"id":"req_1"}}}}"#,
+                delta
+            )
+        };
+        let transport = Arc::new(MockTransport::new());
+        transport.push_events(vec![
+            chunk(r#"{"role":"assistant","content":""}"#),
+            chunk(r#"{"content":"Hel"}"#),
+            chunk(r#"{"content":"lo"}"#),
+            "[DONE]".to_string(),
+        ]);
+        let client = Groq::new("key").with_transport(transport);
+        let timed = client
+            .create_with_timings(
+                RequestBuilder::new("m".into()).with_stream(true),
+                vec![fixtures::user("hello")],
+            )
+            .await?;
+
+        assert!(timed.timings.time_to_first_token.is_some());
+        assert_eq!(timed.timings.inter_token_gaps.len(), 1);
+        assert_eq!(timed.timings.completion_tokens, Some(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn non_stream_call_reports_latency() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(200, fixtures::completion_with_usage("hi", 1, 4));
+        let client = Groq::new("key").with_transport(transport);
+        let timed = client
+            .create_with_timings(
+                RequestBuilder::new("m".into()),
+                vec![fixtures::user("hello")],
+            )
+            .await?;
+
+        assert!(matches!(timed.completion, CompletionOption::NonStream(_)));
+        assert_eq!(timed.timings.completion_tokens, Some(4));
+        assert!(timed.timings.time_to_first_token.is_none());
+        assert!(timed.timings.inter_token_gaps.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn timed_calls_fall_back() -> anyhow::Result<()> {
+        let transport = Arc::new(MockTransport::new());
+        transport
+            .push_json(
+                503,
+                r#"{"error":{"message":"over capacity","type":"internal_server_error","code":"service_unavailable"}}"#,
+            )
+            .push_json(200, fixtures::completion_with_usage("hi", 1, 4));
+        let client = Groq::new("key").with_transport(transport.clone());
+        let req = RequestBuilder::new("m".into())
+            .with_fallback(FallbackPolicy::new().with_model("backup"));
+        let timed = client
+            .create_with_timings(req, vec![fixtures::user("hello")])
+            .await?;
+
+        assert_eq!(timed.timings.completion_tokens, Some(4));
+        assert_eq!(transport.requests()[1].body["model"], "backup");
+        Ok(())
+    }
+}
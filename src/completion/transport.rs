@@ -0,0 +1,638 @@
+use std::{collections::VecDeque, fmt::Debug, sync::Mutex};
+
+use futures::{future::BoxFuture, stream::BoxStream, StreamExt};
+use reqwest::header;
+use reqwest_eventsource::{Event, EventSource};
+use zeroize::Zeroize;
+
+use super::cassette::redact_headers;
+
+/// The chat completion endpoint of groq's OpenAI compatible API
+pub const COMPLETIONS_URL: &str = "https://api.groq.com/openai/v1/chat/completions";
+
+/// A fully prepared HTTP request with a json body.
+/// The headers already include the `authorization` header, which is redacted in `Debug` and wiped
+/// from memory when the request is dropped.
+#[derive(Clone, PartialEq)]
+pub struct HttpRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: serde_json::Value,
+}
+
+impl Debug for HttpRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpRequest")
+            .field("url", &self.url)
+            .field("headers", &redact_headers(self.headers.clone()))
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+impl Drop for HttpRequest {
+    fn drop(&mut self) {
+        zeroize_credentials(&mut self.headers);
+    }
+}
+
+/// A fully buffered HTTP response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// A single part of a multipart/form-data body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipartPart {
+    pub name: String,
+    pub file_name: Option<String>,
+    pub mime: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// A prepared multipart/form-data request, used by the file based endpoints (e.g. audio).
+/// Like [`HttpRequest`], the `authorization` header is redacted in `Debug` and wiped on drop.
+#[derive(Clone, PartialEq)]
+pub struct MultipartRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub parts: Vec<MultipartPart>,
+}
+
+impl Debug for MultipartRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultipartRequest")
+            .field("url", &self.url)
+            .field("headers", &redact_headers(self.headers.clone()))
+            .field("parts", &self.parts)
+            .finish()
+    }
+}
+
+impl Drop for MultipartRequest {
+    fn drop(&mut self) {
+        zeroize_credentials(&mut self.headers);
+    }
+}
+
+/// Wipes the value of the credential bearing headers
+pub(crate) fn zeroize_credentials(headers: &mut [(String, String)]) {
+    for (name, value) in headers {
+        if is_credential(name) {
+            value.zeroize();
+        }
+    }
+}
+
+pub(crate) fn is_credential(header: &str) -> bool {
+    header.eq_ignore_ascii_case(header::AUTHORIZATION.as_str())
+}
+
+/// Events yielded by a Server Sent Event stream.
+/// - Open, the connection has been established
+/// - Message, the data field of a received event
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SseEvent {
+    Open,
+    Message(String),
+}
+
+pub type EventStream = BoxStream<'static, anyhow::Result<SseEvent>>;
+
+/// Result of opening a Server Sent Event stream.
+/// - Events, the server accepted the request and is sending events
+/// - Rejected, the server answered with a non success status, the body holds groq's error object
+pub enum StreamReply {
+    Events(EventStream),
+    Rejected(HttpResponse),
+}
+
+/// The HTTP stack used by [`Groq`](super::client::Groq).
+///
+/// [`ReqwestTransport`] is used by default. A custom implementation can be installed with
+/// [`Groq::with_transport`](super::client::Groq::with_transport) to mock groq in tests, add
+/// tracing, sign requests for a proxy or run on a different runtime.
+pub trait Transport: Debug + Send + Sync {
+    /// Sends a json request and buffers the whole response.
+    fn send_json(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<HttpResponse>>;
+
+    /// Opens a Server Sent Event stream for a json request.
+    fn open_stream(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<StreamReply>>;
+
+    /// Sends a multipart/form-data request and buffers the whole response.
+    /// Transports that do not need file uploads can rely on the default, which always fails.
+    fn send_multipart(
+        &self,
+        request: MultipartRequest,
+    ) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        Box::pin(async move {
+            anyhow::bail!(
+                "multipart requests to '{}' are not supported by this transport",
+                request.url
+            )
+        })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for std::sync::Arc<T> {
+    fn send_json(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        (**self).send_json(request)
+    }
+
+    fn open_stream(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<StreamReply>> {
+        (**self).open_stream(request)
+    }
+
+    fn send_multipart(
+        &self,
+        request: MultipartRequest,
+    ) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        (**self).send_multipart(request)
+    }
+}
+
+/// Default [`Transport`] backed by [`reqwest`] and [`reqwest_eventsource`].
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(client: reqwest::Client) -> Self {
+        //! Reuses an existing reqwest::Client, e.g. one configured with proxies or timeouts
+        Self { client }
+    }
+
+    fn post(&self, url: &str, headers: &[(String, String)]) -> reqwest::RequestBuilder {
+        headers
+            .iter()
+            .fold(self.client.post(url), |builder, (name, value)| {
+                match header::HeaderValue::from_str(value) {
+                    Ok(mut value) => {
+                        // keeps the key out of reqwest's own Debug output
+                        value.set_sensitive(is_credential(name));
+                        builder.header(name.as_str(), value)
+                    }
+                    // reqwest reports the invalid value when the request is sent
+                    Err(_) => builder.header(name.as_str(), value.as_str()),
+                }
+            })
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send_json(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        Box::pin(async move {
+            let res = self
+                .post(&request.url, &request.headers)
+                .json(&request.body)
+                .send()
+                .await?;
+            into_http_response(res).await
+        })
+    }
+
+    fn open_stream(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<StreamReply>> {
+        /* REMARK:
+         * https://github.com/jpopesculian/reqwest-eventsource/
+         * https://parsec.cloud/en/how-the-reqwest-http-client-streams-responses-in-a-web-context/
+         */
+        Box::pin(async move {
+            let mut source = EventSource::new(
+                self.post(&request.url, &request.headers)
+                    .header(header::ACCEPT, "text/event-stream")
+                    .json(&request.body),
+            )?;
+            // Reconnecting would silently submit the completion again, see `ReconnectPolicy`.
+            source.set_retry_policy(Box::new(reqwest_eventsource::retry::Never));
+            // The status code is only known once the first event is polled.
+            match source.next().await {
+                Some(Ok(Event::Open)) => {}
+                Some(Ok(Event::Message(message))) => {
+                    anyhow::bail!("received event '{}' before the stream opened", message.data)
+                }
+                Some(Err(reqwest_eventsource::Error::InvalidStatusCode(_, res))) => {
+                    source.close();
+                    return Ok(StreamReply::Rejected(into_http_response(res).await?));
+                }
+                Some(Err(err)) => {
+                    source.close();
+                    return Err(event_error(err));
+                }
+                None => anyhow::bail!("event stream closed before it opened"),
+            }
+
+            let events = futures::stream::once(async { Ok(SseEvent::Open) }).chain(
+                futures::stream::unfold(Some(source), |source| async move {
+                    let mut source = source?;
+                    match source.next().await {
+                        Some(Ok(Event::Open)) => Some((Ok(SseEvent::Open), Some(source))),
+                        Some(Ok(Event::Message(message))) => {
+                            Some((Ok(SseEvent::Message(message.data)), Some(source)))
+                        }
+                        Some(Err(reqwest_eventsource::Error::StreamEnded)) | None => {
+                            source.close();
+                            None
+                        }
+                        Some(Err(err)) => {
+                            source.close();
+                            Some((Err(event_error(err)), None))
+                        }
+                    }
+                }),
+            );
+            Ok(StreamReply::Events(events.boxed()))
+        })
+    }
+
+    fn send_multipart(
+        &self,
+        mut request: MultipartRequest,
+    ) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        Box::pin(async move {
+            let mut form = reqwest::multipart::Form::new();
+            for part in std::mem::take(&mut request.parts) {
+                let mut body = reqwest::multipart::Part::bytes(part.data);
+                if let Some(file_name) = part.file_name {
+                    body = body.file_name(file_name);
+                }
+                if let Some(mime) = part.mime {
+                    body = body.mime_str(&mime)?;
+                }
+                form = form.part(part.name, body);
+            }
+            let res = self
+                .post(&request.url, &request.headers)
+                .multipart(form)
+                .send()
+                .await?;
+            into_http_response(res).await
+        })
+    }
+}
+
+/// A reply queued on a [`MockTransport`].
+/// - Response, a buffered response; answers a stream request with [`StreamReply::Rejected`]
+/// - Events, the data of the Server Sent Events to emit (include the final `[DONE]`)
+/// - Stalled, like Events but the stream then hangs forever instead of closing
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockReply {
+    Response(HttpResponse),
+    Events(Vec<String>),
+    Stalled(Vec<String>),
+}
+
+/// [`Transport`] answering requests with queued replies in FIFO order, for testing code built on
+/// top of [`Groq`](super::client::Groq) without reaching the network.
+/// Every request it receives is kept and can be inspected with [`MockTransport::requests`].
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    replies: Mutex<VecDeque<MockReply>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_json(&self, status: u16, body: impl Into<String>) -> &Self {
+        //! Queues a buffered json response
+        self.push(MockReply::Response(HttpResponse {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }))
+    }
+
+    pub fn push_events(&self, events: Vec<String>) -> &Self {
+        //! Queues a successful event stream
+        self.push(MockReply::Events(events))
+    }
+
+    pub fn push_stalled_events(&self, events: Vec<String>) -> &Self {
+        //! Queues an event stream that stops sending after `events` without ever closing
+        self.push(MockReply::Stalled(events))
+    }
+
+    pub fn push(&self, reply: MockReply) -> &Self {
+        self.replies.lock().unwrap().push_back(reply);
+        self
+    }
+
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        //! Returns the requests received so far, in order
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn next_reply(&self, request: HttpRequest) -> anyhow::Result<MockReply> {
+        let url = request.url.clone();
+        self.requests.lock().unwrap().push(request);
+        self.replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("no reply queued on the mock transport for '{}'", url))
+    }
+}
+
+impl Transport for MockTransport {
+    fn send_json(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+        let reply = self.next_reply(request);
+        Box::pin(async move {
+            match reply? {
+                MockReply::Response(res) => Ok(res),
+                MockReply::Events(_) | MockReply::Stalled(_) => {
+                    anyhow::bail!("an event stream was queued for a json request")
+                }
+            }
+        })
+    }
+
+    fn open_stream(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<StreamReply>> {
+        let reply = self.next_reply(request);
+        Box::pin(async move {
+            match reply? {
+                MockReply::Response(res) => Ok(StreamReply::Rejected(res)),
+                MockReply::Events(events) => Ok(StreamReply::Events(
+                    futures::stream::iter(
+                        std::iter::once(SseEvent::Open)
+                            .chain(events.into_iter().map(SseEvent::Message))
+                            .map(Ok),
+                    )
+                    .boxed(),
+                )),
+                MockReply::Stalled(events) => Ok(StreamReply::Events(
+                    futures::stream::iter(
+                        std::iter::once(SseEvent::Open)
+                            .chain(events.into_iter().map(SseEvent::Message))
+                            .map(Ok),
+                    )
+                    .chain(futures::stream::pending())
+                    .boxed(),
+                )),
+            }
+        })
+    }
+}
+
+async fn into_http_response(res: reqwest::Response) -> anyhow::Result<HttpResponse> {
+    Ok(HttpResponse {
+        status: res.status().as_u16(),
+        headers: collect_headers(res.headers()),
+        body: res.text().await?,
+    })
+}
+
+/// Keeps the [`reqwest::Error`] of a failed event stream as the error itself, so that
+/// [`is_retryable`](super::retry::is_retryable) can tell transport failures apart.
+fn event_error(err: reqwest_eventsource::Error) -> anyhow::Error {
+    match err {
+        reqwest_eventsource::Error::Transport(err) => err.into(),
+        err => err.into(),
+    }
+}
+
+fn collect_headers(headers: &header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Fixtures shared by the tests of the crate
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use serde_json::json;
+
+    use super::MockTransport;
+    use crate::completion::message::Message;
+
+    pub(crate) fn completion(content: &str) -> String {
+        completion_with_usage(content, 1, 1)
+    }
+
+    pub(crate) fn completion_with_usage(
+        content: &str,
+        prompt_tokens: u32,
+        completion_tokens: u32,
+    ) -> String {
+        response(
+            json!({ "role": "assistant", "content": content }),
+            "stop",
+            prompt_tokens,
+            completion_tokens,
+        )
+    }
+
+    pub(crate) fn tool_call(id: &str, name: &str, arguments: &str) -> String {
+        let call = json!({
+            "id": id,
+            "type": "function",
+            "function": { "name": name, "arguments": arguments },
+        });
+        response(
+            json!({ "role": "assistant", "content": null, "tool_calls": [call] }),
+            "tool_calls",
+            1,
+            1,
+        )
+    }
+
+    fn response(
+        message: serde_json::Value,
+        finish_reason: &str,
+        prompt_tokens: u32,
+        completion_tokens: u32,
+    ) -> String {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1718000000,
+            "model": "m",
+            "system_fingerprint": null,
+            "choices": [{
+                "index": 0,
+                "message": message,
+                "finish_reason": finish_reason,
+                "logprobs": null,
+            }],
+            "usage": {
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": completion_tokens,
+                "total_tokens": prompt_tokens + completion_tokens,
+                "prompt_time": 0.1,
+                "completion_time": 0.1,
+                "total_time": 0.2,
+            },
+        })
+        .to_string()
+    }
+
+    pub(crate) fn user(content: &str) -> Message {
+        Message::UserMessage {
+            content: Some(content.to_string()),
+            name: None,
+            role: Some("user".to_string()),
+            tool_call_id: None,
+        }
+    }
+
+    impl MockTransport {
+        pub(crate) fn push_completion(&self, content: &str) -> &Self {
+            //! Queues a successful answer with `content`, using 2 tokens
+            self.push_json(200, completion(content))
+        }
+
+        pub(crate) fn push_tool_call(&self, id: &str, name: &str, arguments: &str) -> &Self {
+            //! Queues a successful answer calling a single tool, using 2 tokens
+            self.push_json(200, tool_call(id, name, arguments))
+        }
+    }
+}
+
+#[cfg(test)]
+mod transport_test {
+    use std::sync::{Arc, Mutex};
+
+    use futures::future::BoxFuture;
+
+    use super::{
+        event_error, fixtures, HttpRequest, HttpResponse, MockTransport, StreamReply, Transport,
+    };
+    use crate::completion::{
+        client::{CompletionOption, Groq},
+        message::Message,
+        request::builder::RequestBuilder,
+        response::ErrorResponse,
+        retry::is_retryable,
+    };
+
+    #[derive(Debug)]
+    struct CannedTransport {
+        status: u16,
+        body: String,
+        seen: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl Transport for CannedTransport {
+        fn send_json(&self, request: HttpRequest) -> BoxFuture<'_, anyhow::Result<HttpResponse>> {
+            self.seen.lock().unwrap().push(request);
+            Box::pin(async move {
+                Ok(HttpResponse {
+                    status: self.status,
+                    headers: Vec::new(),
+                    body: self.body.clone(),
+                })
+            })
+        }
+
+        fn open_stream(&self, _: HttpRequest) -> BoxFuture<'_, anyhow::Result<StreamReply>> {
+            Box::pin(async { anyhow::bail!("streams are not canned") })
+        }
+    }
+
+    fn user_message() -> Message {
+        Message::UserMessage {
+            role: Some("user".to_string()),
+            content: Some("Say hi".to_string()),
+            name: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_transport_serves_completion() -> anyhow::Result<()> {
+        let transport = std::sync::Arc::new(CannedTransport {
+            status: 200,
+            body: fixtures::completion("hi"),
+            seen: Mutex::new(Vec::new()),
+        });
+        let client = Groq::new("key").with_transport(transport.clone());
+
+        let res = client
+            .create(
+                RequestBuilder::new("mixtral-8x7b-32768".into()),
+                vec![user_message()],
+            )
+            .await?;
+        assert!(
+            matches!(res, CompletionOption::NonStream(res) if res.choices[0].message.content == "hi")
+        );
+
+        let seen = transport.seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert!(seen[0]
+            .headers
+            .contains(&("authorization".into(), "Bearer key".into())));
+        assert_eq!(seen[0].body["model"], "mixtral-8x7b-32768");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejected_request_returns_error_object() -> anyhow::Result<()> {
+        let transport = CannedTransport {
+            status: 401,
+            body: r#"{"error":{"message":"Invalid API Key","type":"invalid_request_error"}}"#
+                .into(),
+            seen: Mutex::new(Vec::new()),
+        };
+        let client = Groq::new("").with_transport(transport);
+
+        let err = client
+            .create(
+                RequestBuilder::new("mixtral-8x7b-32768".into()),
+                vec![user_message()],
+            )
+            .await
+            .unwrap_err();
+        let err = err.downcast::<ErrorResponse>()?;
+        assert_eq!(err.code, reqwest::StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sent_requests_redact_the_key() {
+        let transport = Arc::new(MockTransport::new());
+        transport.push_json(401, r#"{"error":{"message":"no","type":"auth"}}"#);
+        let client = Groq::new("gsk_live_123").with_transport(transport.clone());
+        let _ = client
+            .create(RequestBuilder::new("m".into()), vec![user_message()])
+            .await;
+
+        let sent = &transport.requests()[0];
+        assert!(sent
+            .headers
+            .contains(&("authorization".into(), "Bearer gsk_live_123".into())));
+        assert!(!format!("{:?}", sent).contains("gsk_live_123"));
+        assert!(!format!("{:?}", client).contains("gsk_live_123"));
+    }
+
+    #[tokio::test]
+    async fn only_connection_failures_of_streams_are_retryable() {
+        let refused = reqwest::get("http://127.0.0.1:1").await.unwrap_err();
+        let err = event_error(reqwest_eventsource::Error::Transport(refused));
+        assert!(err.downcast_ref::<reqwest::Error>().is_some());
+        assert!(is_retryable(&err));
+
+        let invalid = reqwest::Client::new().get("not a url").build().unwrap_err();
+        assert!(!is_retryable(&event_error(
+            reqwest_eventsource::Error::Transport(invalid)
+        )));
+        assert!(!is_retryable(&event_error(
+            reqwest_eventsource::Error::StreamEnded
+        )));
+    }
+}
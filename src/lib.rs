@@ -11,6 +11,17 @@
 //! cargo add groq-api-rs
 //! ```
 //!
+//! # Features
+//! - `tracing`, emits a `groq.completion` span (model, stream flag, status code, token usage,
+//!   latency, time to first token and retries) for every completion call via [`tracing`](https://docs.rs/tracing)
+//! - `opentelemetry`, emits spans and metrics following the OpenTelemetry GenAI semantic conventions
+//!   (`gen_ai.request.model`, `gen_ai.usage.input_tokens`, `gen_ai.client.operation.duration`, ...)
+//!   through the globally installed providers
+//! - `toml` / `yaml`, load request presets (`request::preset::Presets`) from TOML / YAML files,
+//!   JSON is always supported
+//! - `cli`, builds the `groq` binary: one-shot prompts from arguments or stdin, an interactive chat
+//!   with saved sessions, streamed or JSON output (`cargo install groq-api-rs --features cli`)
+//!
 //! # Example
 //! Request a completion object from Groq
 //! ```
@@ -26,10 +37,11 @@
 //!     let request = builder::RequestBuilder::new("mixtral-8x7b-32768".to_string());
 //!     let api_key = env!("GROQ_API_KEY");
 //!
-//!     let mut client = Groq::new(api_key);
-//!     client.add_messages(messages);
+//!     let client = Groq::new(api_key);
+//!     let mut conversation = client.conversation();
+//!     conversation.add_messages(messages);
 //!
-//!     let res = client.create(request).await;
+//!     let res = conversation.create(request).await;
 //!     assert!(res.is_ok());
 //!     Ok(())
 //! }
@@ -49,15 +61,46 @@
 //!         builder::RequestBuilder::new("mixtral-8x7b-32768".to_string()).with_stream(true);
 //!     let api_key = env!("GROQ_API_KEY");
 //!
-//!     let mut client = Groq::new(api_key);
-//!     client.add_messages(messages);
+//!     let client = Groq::new(api_key);
+//!     let mut conversation = client.conversation();
+//!     conversation.add_messages(messages);
 //!
-//!     let res = client.create(request).await;
+//!     let res = conversation.create(request).await;
 //!     assert!(res.is_ok());
 //!     Ok(())
 //! }
 //! ```
 //!
+//! A single client can be shared across tokio tasks, each task sending its own messages
+//! ```
+//! use groq_api_rs::completion::{client::Groq, message::Message, request::builder};
+//! use std::sync::Arc;
+//!
+//! async fn create_concurrently() -> anyhow::Result<()> {
+//!     let client = Arc::new(Groq::new(env!("GROQ_API_KEY")));
+//!     let tasks: Vec<_> = ["Explain rust", "Explain groq"]
+//!         .into_iter()
+//!         .map(|prompt| {
+//!             let client = client.clone();
+//!             tokio::spawn(async move {
+//!                 let messages = vec![Message::UserMessage {
+//!                     role: Some("user".to_string()),
+//!                     content: Some(prompt.to_string()),
+//!                     name: None,
+//!                     tool_call_id: None,
+//!                 }];
+//!                 let request = builder::RequestBuilder::new("mixtral-8x7b-32768".to_string());
+//!                 client.create(request, messages).await
+//!             })
+//!         })
+//!         .collect();
+//!     for task in tasks {
+//!         assert!(task.await?.is_ok());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
 //! Example that the completion can return Error Object and augmented with HTTP status code.
 //! ```
 //! use groq_api_rs::completion::{client::Groq, message::Message, request::builder};
@@ -72,10 +115,11 @@
 //!         builder::RequestBuilder::new("mixtral-8x7b-32768".to_string()).with_stream(true);
 //!     let api_key = "";
 //!
-//!     let mut client = Groq::new(api_key);
-//!     client.add_messages(messages);
+//!     let client = Groq::new(api_key);
+//!     let mut conversation = client.conversation();
+//!     conversation.add_messages(messages);
 //!
-//!     let res = client.create(request).await;
+//!     let res = conversation.create(request).await;
 //!     assert!(res.is_err());
 //!     eprintln!("{}", res.unwrap_err());
 //!     Ok(())